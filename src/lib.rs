@@ -0,0 +1,7291 @@
+//! User friendly bootstrapping of tools that require per-project installs.
+//!
+//! This is the launcher logic behind the get-it-going executable, exposed so
+//! it can be embedded in other programs. Load an [`AppConfig`] and pass it to
+//! [`launch`]. Logging is done through the [`log`] facade, so is only output if
+//! you've installed a logger. The executable's own logger is behind the
+//! default `cli` feature, so it can be left out with `default-features = false`
+
+#![deny(clippy::undocumented_unsafe_blocks)]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+#[cfg(windows)]
+use std::os::windows::process::{CommandExt, ExitStatusExt};
+#[cfg(unix)]
+use std::sync::atomic::AtomicI32;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, BTreeMap, VecDeque},
+    env,
+    ffi::{OsStr, OsString},
+    fmt::{self, Write as _},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, IsTerminal, Read, Write},
+    marker::PhantomData,
+    path::{Component, Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, Once, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context};
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use once_cell::sync::{Lazy, OnceCell};
+use regex_lite::Regex;
+use serde::{
+    de::{
+        value::{
+            MapAccessDeserializer, SeqAccessDeserializer, StrDeserializer,
+        },
+        Error, MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use shlex::Shlex;
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+compile_error!("unsupported OS: only Windows, MacOS, and Linux currently");
+
+/// The name of the program being shimmed, unless overridden by `GIG_OVERRIDE`.
+/// This is the name gig was invoked as (argv\[0\]), so a symlink to gig gets
+/// the symlink's name, or the name of the executable itself (with symlinks
+/// resolved) if `GIG_NAME_FROM` is `exe`
+pub static NAME: Lazy<Box<str>> =
+    Lazy::new(|| match env::var("GIG_OVERRIDE") {
+        Ok(name) => name.into_boxed_str(),
+        Err(_) => {
+            let from_exe = env::var("GIG_NAME_FROM")
+                .is_ok_and(|from| from.eq_ignore_ascii_case("exe"));
+            let invoked_as = env::args_os()
+                .next()
+                .filter(|_| !from_exe)
+                .map(PathBuf::from)
+                .filter(|arg0| arg0.file_stem().is_some());
+            let executable = invoked_as.unwrap_or_else(|| {
+                env::current_exe().expect("can't access own path")
+            });
+            executable.file_stem().unwrap().to_string_lossy().into()
+        },
+    });
+
+static SYSTEM_WIDE_CONFIG_DIRECTORY: Lazy<&Path> = Lazy::new(|| {
+    if cfg!(windows) {
+        Path::new("C:\\Program Files\\Common Files\\get-it-going")
+    } else if cfg!(target_os = "macos") {
+        Path::new("/Library/Application Support/get-it-going")
+    } else if cfg!(target_os = "linux") {
+        Path::new("/etc/get-it-going")
+    } else {
+        unreachable!("compile_error! for unsupported OSes should prevent this")
+    }
+});
+
+static USER_CONFIG_DIRECTORY: Lazy<Option<PathBuf>> =
+    Lazy::new(|| dirs::config_dir().map(|dir| dir.join("get-it-going")));
+
+/// The directories to search for config files instead of the defaults, from
+/// `GIG_CONFIG_PATH` (separated like $PATH)
+static CONFIG_SEARCH_PATH: Lazy<Option<Vec<PathBuf>>> = Lazy::new(|| {
+    let config_path = env::var_os("GIG_CONFIG_PATH")
+        .filter(|config_path| !config_path.is_empty())?;
+    Some(
+        env::split_paths(&config_path)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect(),
+    )
+});
+
+/// The file extensions config files can have, in order of preference
+pub const CONFIG_EXTENSIONS: &[&str] = &[
+    "toml",
+    #[cfg(feature = "yaml")]
+    "yaml",
+    #[cfg(feature = "yaml")]
+    "yml",
+    #[cfg(feature = "json")]
+    "json",
+];
+
+/// The profile from the config's `[profiles]` to use, set by `GIG_PROFILE`
+pub static PROFILE: Lazy<Option<Box<str>>> = Lazy::new(|| {
+    env::var("GIG_PROFILE")
+        .ok()
+        .filter(|profile| !profile.is_empty())
+        .map(String::into_boxed_str)
+});
+
+/// Whether commands should only be logged, instead of being run
+pub static DRY_RUN: Lazy<bool> =
+    Lazy::new(|| env::var_os("GIG_DRY_RUN").is_some());
+
+/// Whether to print a JSON summary of what will be run to stdout, before
+/// running it
+pub static PORCELAIN: Lazy<bool> =
+    Lazy::new(|| env::var_os("GIG_PORCELAIN").is_some());
+
+/// Whether output written to stderr should be coloured. See
+/// <https://no-color.org>
+pub static COLOR: Lazy<bool> = Lazy::new(|| {
+    let no_color = env::var_os("NO_COLOR")
+        .is_some_and(|value| !value.is_empty())
+        || env::var_os("GIG_NO_COLOR").is_some();
+    !no_color && io::stderr().is_terminal()
+});
+
+/// Whether to log how long each command takes to run
+pub static TIMING: Lazy<bool> =
+    Lazy::new(|| env::var_os("GIG_TIMING").is_some());
+
+/// Whether to merge every config file found, instead of only using the first
+pub static CONFIG_MERGE: Lazy<bool> =
+    Lazy::new(|| env::var_os("GIG_CONFIG_MERGE").is_some());
+
+/// How many instances of gig this one was started by, from `GIG_DEPTH`, which
+/// is set for everything gig runs
+static DEPTH: Lazy<u32> = Lazy::new(|| {
+    env::var("GIG_DEPTH")
+        .ok()
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(0)
+});
+
+/// How deep gig can be started by itself before it's assumed to be running
+/// itself in a loop
+const MAX_DEPTH: u32 = 10;
+
+/// Sets `GIG_DEPTH` for something gig runs, so if it starts gig again, that
+/// can tell how deep it is. This should be done for every command gig runs
+fn set_depth(command: &mut Command) {
+    command.env("GIG_DEPTH", (*DEPTH + 1).to_string());
+}
+
+static CWD: Lazy<PathBuf> = Lazy::new(|| {
+    env::current_dir()
+        .expect("get-it-going must have access to current working directory")
+});
+
+/// Which part of the config the final exit status came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// The program in `run`
+    Run,
+    /// One of the fallbacks, run instead of (or after) `run`
+    Fallback,
+    /// A `before_run` step that failed, so nothing else was run
+    BeforeRun,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Run => "run",
+            Stage::Fallback => "fallback",
+            Stage::BeforeRun => "before_run",
+        })
+    }
+}
+
+/// Runs the program described by `config`, passing it `args`. This will run
+/// the `before_run` steps, any fallbacks, and the `after_run` steps as needed,
+/// returning the exit status of whichever of `run` or the fallbacks ran last,
+/// and which it was. If a `before_run` step fails, its exit status is returned
+/// instead, after logging why. A dry run instead errors if any of the programs
+/// it would have run can't be found
+pub fn launch(
+    config: &AppConfig,
+    args: impl Iterator<Item = OsString>,
+) -> anyhow::Result<(ExitStatus, Stage)> {
+    let args = args.collect::<Vec<_>>();
+    // How many of the commands a dry run would've run can't be, because their
+    // program can't be found or isn't executable
+    let unresolved = AtomicUsize::new(0);
+    let result = match launch_steps(config, &args, &unresolved) {
+        Err(why) => match why.downcast_ref::<BeforeRunFailed>() {
+            Some(failed) => {
+                error!("{why:#}");
+                Ok((failed.status, Stage::BeforeRun))
+            },
+            None => Err(why),
+        },
+        result => result,
+    };
+    let unresolved = unresolved.into_inner();
+    if result.is_ok() && unresolved > 0 {
+        bail!(
+            "{unresolved} command{} in the dry run wouldn't be able to run",
+            if unresolved == 1 { "" } else { "s" },
+        );
+    }
+    result
+}
+
+/// Does the work of [`launch`]
+fn launch_steps(
+    config: &AppConfig,
+    args: &[OsString],
+    unresolved: &AtomicUsize,
+) -> anyhow::Result<(ExitStatus, Stage)> {
+    // A shim that runs itself (e.g. run.path or $PATH leading back to gig)
+    // would otherwise keep starting itself forever
+    if *DEPTH >= MAX_DEPTH {
+        bail!(
+            "gig has been started by itself {} times in a row, so it's likely \
+             running itself in a loop. Check that run can't find gig instead \
+             of the program (e.g. through $PATH)",
+            *DEPTH,
+        );
+    }
+
+    if config.deny_elevated {
+        match is_elevated() {
+            Ok(false) => {},
+            Ok(true) => bail!(
+                "refusing to run with elevated privileges, as deny_elevated \
+                 is set"
+            ),
+            Err(why) => bail!(
+                "couldn't check for elevated privileges, which deny_elevated \
+                 needs: {why}"
+            ),
+        }
+    }
+
+    if config.path == Path::new(STDIN_CONFIG)
+        && matches!(config.run.stdin, InputMode::Inherit)
+    {
+        warn!(
+            "the config was read from stdin, so there's nothing left on stdin \
+             for the program to read"
+        );
+    }
+
+    // Step 1: work out if we're good to go, and where to run from
+    let root = match config.get_root() {
+        Ok(root) => root,
+        Err(why) => {
+            let not_found = why.downcast::<RootNotFound>()?;
+            // If we're not good to go, do we have a fallback to run instead?
+            let fallbacks = config.generate_fallback(args);
+            if fallbacks.is_empty() {
+                let hints = not_found
+                    .hints
+                    .iter()
+                    .map(|(path, hint)| {
+                        format!("\n  {}: {hint}", path.display())
+                    })
+                    .collect::<String>();
+                bail!("couldn't find required files: {not_found}{hints}");
+            }
+            info!("unable to locate required files, running fallback");
+            if let (true, Some(Ok(command))) = (*PORCELAIN, fallbacks.first()) {
+                print_porcelain(config, None, command, true);
+            }
+            // Without a root, any before_run steps the fallback wants are run
+            // in CWD, and cleaned up after like they would be for run
+            let mut ran_before = false;
+            let result = run_fallbacks(
+                config.fallback.iter().zip(fallbacks),
+                &format!("required files not found ({not_found})"),
+                unresolved,
+                |fallback, command| {
+                    if !fallback.run_before || ran_before {
+                        return Ok(());
+                    }
+                    ran_before = true;
+                    info!(
+                        "running before_run in {} for fallback",
+                        CWD.display()
+                    );
+                    if let Some((name, value)) =
+                        run_before_run_steps(config, &CWD, unresolved)?
+                    {
+                        command.set_captured_env(name, &value);
+                    }
+                    Ok(())
+                },
+            )
+            .map(|status| (status, Stage::Fallback));
+            if ran_before {
+                for (index, after_run) in config.after_run.iter().enumerate() {
+                    run_after_run(config, after_run, index, &CWD, unresolved);
+                }
+            }
+            return result;
+        },
+    };
+    let root = if config.canonicalize_root {
+        match fs::canonicalize(&root) {
+            Ok(canonical_root) => {
+                debug!("canonicalised root to {}", canonical_root.display());
+                canonical_root.into()
+            },
+            Err(why) => {
+                warn!("failed to canonicalise {}: {why}", root.display());
+                root
+            },
+        }
+    } else {
+        root
+    };
+
+    let result = launch_in_root(config, &root, args, unresolved);
+    // Step 5: clean up, however the other steps went
+    for (index, after_run) in config.after_run.iter().enumerate() {
+        run_after_run(config, after_run, index, &root, unresolved);
+    }
+    result
+}
+
+/// Runs every `before_run` step in `root`, with consecutive parallel steps run
+/// at the same time. Returns the variable for `run` that the last step's
+/// stdout was captured into, if any
+fn run_before_run_steps<'a>(
+    config: &'a AppConfig,
+    root: &Path,
+    unresolved: &AtomicUsize,
+) -> anyhow::Result<Option<(&'a str, String)>> {
+    let mut index = 0;
+    let mut captured = None;
+    for group in config.before_run.chunk_by(|a, b| a.parallel && b.parallel) {
+        if let [before_run] = group {
+            captured =
+                run_before_run(config, before_run, index, root, unresolved)?;
+        } else {
+            let results = thread::scope(|scope| {
+                let handles = group
+                    .iter()
+                    .zip(index..)
+                    .map(|(before_run, index)| {
+                        scope.spawn(move || {
+                            run_before_run(
+                                config, before_run, index, root, unresolved,
+                            )
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("before_run panicked"))
+                    .collect::<Vec<_>>()
+            });
+            // Every step in the group is waited for before failing
+            let mut results =
+                results.into_iter().collect::<anyhow::Result<Vec<_>>>()?;
+            captured = results.pop().flatten();
+        }
+        index += group.len();
+    }
+    Ok(captured)
+}
+
+/// Steps 2 to 4 of [`launch`], once the root has been found
+fn launch_in_root(
+    config: &AppConfig,
+    root: &Path,
+    args: &[OsString],
+    unresolved: &AtomicUsize,
+) -> anyhow::Result<(ExitStatus, Stage)> {
+    // Step 2: run before_run tasks/scripts
+    let captured = run_before_run_steps(config, root, unresolved)?;
+
+    // Step 3: build and spawn process
+    let (target, target_args) = config.run_target(root, args)?;
+    let mut attempt = 0;
+    let status = loop {
+        let mut command =
+            config.generate_run_target(&target, root, target_args)?;
+        if let Some((name, value)) = &captured {
+            command.set_captured_env(name, value);
+        }
+        // If the program to run doesn't exist, do we have a fallback for it?
+        // Shell commands can't be checked
+        if attempt == 0
+            && !config.fallback.is_empty()
+            && !matches!(*target, Run::Shell(_))
+        {
+            let program = Path::new(command.command.get_program());
+            let dir = command.command.get_current_dir().unwrap_or(root);
+            if find_program(program, dir).is_none() {
+                let fallbacks = config.generate_fallback(args);
+                info!("{} can't be found, running fallback", program.display());
+                if let (true, Some(Ok(command))) =
+                    (*PORCELAIN, fallbacks.first())
+                {
+                    print_porcelain(config, Some(root), command, true);
+                }
+                return run_fallbacks(
+                    config.fallback.iter().zip(fallbacks),
+                    &format!("{} not found", program.display()),
+                    unresolved,
+                    |_, _| Ok(()),
+                )
+                .map(|status| (status, Stage::Fallback));
+            }
+        }
+        if *PORCELAIN && attempt == 0 {
+            print_porcelain(config, Some(root), &command, false);
+        }
+        // Otherwise it's already logged when it's run
+        if config.echo_command && !log_enabled!(Level::Info) {
+            if *COLOR {
+                eprintln!("\x1b[2m+ {command}\x1b[0m");
+            } else {
+                eprintln!("+ {command}");
+            }
+        }
+        let program = PathBuf::from(command.command.get_program());
+        let dir = command.command.get_current_dir().unwrap_or(root);
+        // Say where the program was looked for, as the io::Error won't
+        let context = if program.components().count() > 1 {
+            format!("couldn't run {}", dir.join(&program).display())
+        } else {
+            format!("couldn't run {} from $PATH", program.display())
+        };
+        let context = if dir == root {
+            format!("{context} (root {})", root.display())
+        } else {
+            format!("{context} (in {}, root {})", dir.display(), root.display())
+        };
+        let status = command.status(unresolved).context(context)?;
+        if attempt >= config.run.retries || !config.run.should_retry(status) {
+            break status;
+        }
+        attempt += 1;
+        let delay = config.run.retry_delay(attempt);
+        warn!(
+            "run returned a non-zero status, retrying in {} (attempt {} of {})",
+            humantime::format_duration(delay),
+            attempt + 1,
+            config.run.retries + 1,
+        );
+        thread::sleep(delay);
+    };
+
+    // Step 4: if the process exited in a way that means it's not going to work,
+    // do we have a fallback to run instead?
+    match status.code() {
+        Some(code) if config.fallback_on_exit_codes.contains(&code) => {
+            let fallbacks = config.generate_fallback(args);
+            if fallbacks.is_empty() {
+                return Ok((status, Stage::Run));
+            }
+            info!("run exited with status {code}, running fallback");
+            run_fallbacks(
+                config.fallback.iter().zip(fallbacks),
+                &format!("run exited with status {code}"),
+                unresolved,
+                |_, _| Ok(()),
+            )
+            .map(|status| (status, Stage::Fallback))
+        },
+        _ => Ok((status, Stage::Run)),
+    }
+}
+
+/// Runs an `after_run` step, only logging if it fails, so every step gets run
+/// and gig still exits with run's status
+fn run_after_run(
+    config: &AppConfig,
+    after_run: &BeforeRun,
+    index: usize,
+    root: &Path,
+    unresolved: &AtomicUsize,
+) {
+    let label = after_run.labelled("after_run", index);
+    let output = config
+        .generate_before_run(after_run, root)
+        .and_then(|command| command.output(unresolved));
+    match output {
+        Ok(output) if output.status.success() => {},
+        Ok(CommandOutput { status, stderr, .. }) => {
+            let stderr = stderr
+                .filter(|stderr| !stderr.trim().is_empty())
+                .map_or(String::new(), |stderr| {
+                    format!(", stderr:\n{}", stderr.trim_end())
+                });
+            warn!("{label} returned {status}{stderr}");
+        },
+        Err(why) => warn!("failed to run {label}: {why:#}"),
+    }
+}
+
+/// The version of the JSON printed by [`print_porcelain`], to be incremented
+/// if its existing fields change
+const PORCELAIN_SCHEMA_VERSION: u32 = 1;
+
+/// Prints a JSON summary of what gig decided to run to stdout, for tools that
+/// wrap gig. This must be done before the command is spawned, so it doesn't end
+/// up mixed in with the command's own output
+fn print_porcelain(
+    config: &AppConfig,
+    root: Option<&Path>,
+    command: &LoggedCommand,
+    fallback: bool,
+) {
+    let json_path =
+        |path: &Path| JsonString(&path.to_string_lossy()).to_string();
+    let command = &command.command;
+    let args = command
+        .get_args()
+        .map(|arg| JsonString(&arg.to_string_lossy()).to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let fields = [
+        ("schema_version", PORCELAIN_SCHEMA_VERSION.to_string()),
+        ("config", json_path(&config.path)),
+        ("root", root.map_or("null".to_owned(), json_path)),
+        ("fallback", fallback.to_string()),
+        ("program", json_path(Path::new(command.get_program()))),
+        ("args", format!("[{args}]")),
+        (
+            "working_directory",
+            command
+                .get_current_dir()
+                .map_or("null".to_owned(), json_path),
+        ),
+    ]
+    .map(|(key, value)| format!("{}:{value}", JsonString(key)))
+    .join(",");
+    let mut stdout = io::stdout().lock();
+    let printed = writeln!(stdout, "{{{fields}}}").and_then(|_| stdout.flush());
+    if let Err(why) = printed {
+        warn!("couldn't print porcelain output: {why}");
+    }
+}
+
+/// Runs a `before_run` step, unless it's up to date. `index` is its position in
+/// `before_run`, to identify it by if it has no name
+fn run_before_run<'a>(
+    config: &AppConfig,
+    before_run: &'a BeforeRun,
+    index: usize,
+    root: &Path,
+    unresolved: &AtomicUsize,
+) -> anyhow::Result<Option<(&'a str, String)>> {
+    let label = before_run.label(index);
+    if before_run.is_up_to_date(root) {
+        info!("{label} skipped (up to date)");
+        return Ok(None);
+    }
+    let command = config
+        .generate_before_run(before_run, root)
+        .with_context(|| format!("failed to run {label}"))?;
+    if before_run.parallel {
+        info!("{label} started");
+    }
+    let CommandOutput {
+        status,
+        stdout,
+        stderr,
+    } = command
+        .output(unresolved)
+        .with_context(|| format!("failed to run {label}"))?;
+    if before_run.parallel {
+        info!("{label} finished with {status}");
+    }
+    if !status.success() {
+        let stderr = stderr
+            .filter(|stderr| !stderr.trim().is_empty())
+            .map_or(String::new(), |stderr| {
+                format!(", stderr:\n{}", stderr.trim_end())
+            });
+        if before_run.allow_failure {
+            warn!(
+                "{label} returned a non-zero status, continuing anyway{stderr}"
+            );
+        } else {
+            return Err(BeforeRunFailed {
+                message: format!("{label} returned a non-zero status{stderr}"),
+                status,
+            }
+            .into());
+        }
+    }
+    let captured = before_run.capture_into.as_deref().zip(stdout);
+    Ok(captured.map(|(name, stdout)| (name, stdout.trim().to_owned())))
+}
+
+/// Prints get-it-going's version, the name it's running as, and the config
+/// file and root it would use, without running anything
+pub fn info() {
+    println!("get-it-going v{}", env!("CARGO_PKG_VERSION"));
+    println!("name:     {}", NAME.as_ref());
+    if let Some(profile) = PROFILE.as_deref() {
+        println!("profile:  {profile}");
+    }
+    let config = AppConfig::find().and_then(|config_files| {
+        for config_file in &config_files {
+            println!("config:   {}", config_file.display());
+        }
+        AppConfig::load_from_paths(&config_files)
+    });
+    match config {
+        Ok(config) => match config.get_root() {
+            Ok(root) => println!("root:     {}", root.display()),
+            Err(why) => match why.downcast_ref::<RootNotFound>() {
+                Some(not_found) => println!("root:     not found, {not_found}"),
+                None => println!("root:     unusable ({why:#})"),
+            },
+        },
+        Err(why) => println!("config:   unusable ({why:#})"),
+    }
+}
+
+/// Prints every path a config file is looked for at, in order, and whether
+/// each exists. The first one found is the one that would be used, or the
+/// first in each directory with [`CONFIG_MERGE`]
+pub fn list_configs() {
+    if let Some(config_file) = env::var_os("GIG_CONFIG") {
+        let config_file = Path::new(&config_file);
+        let state = if config_file.is_file() {
+            "using  "
+        } else {
+            "missing"
+        };
+        println!("{state} {} (from GIG_CONFIG)", config_file.display());
+        return;
+    }
+    let mut found = false;
+    for dir in AppConfig::search_dirs() {
+        // Merging uses a config from every directory
+        if *CONFIG_MERGE {
+            found = false;
+        }
+        for extension in CONFIG_EXTENSIONS {
+            let config_file = dir.join(format!("{}.{extension}", &*NAME));
+            let state = if !config_file.exists() {
+                "missing"
+            } else if found {
+                "found  "
+            } else {
+                found = true;
+                "using  "
+            };
+            println!("{state} {}", config_file.display());
+        }
+    }
+}
+
+/// Prints the config that would be used as TOML, with everything that changes
+/// it applied (`extends`, platform overrides, profiles, `GIG_CONFIG_MERGE`,
+/// and `required_files = { from = ... }`). It can be saved and loaded as a
+/// config itself
+pub fn dump_config() -> anyhow::Result<()> {
+    let config_files = AppConfig::find()?;
+    let config = merged_table(&config_files)?;
+    let config = toml::to_string(&config).context("couldn't write config")?;
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(config.as_bytes())
+        .and_then(|_| stdout.flush())
+        .context("couldn't write to stdout")?;
+    // Still say if it isn't valid, as that may be why it's being looked at
+    if let Err(why) =
+        toml::Value::Table(toml::from_str(&config)?).try_into::<AppConfig>()
+    {
+        warn!("this config won't load: {why:#}");
+    }
+    Ok(())
+}
+
+/// Reports on whether the config can be loaded, the required files can be
+/// found, and the program to run exists, without running anything. Returns
+/// whether all of these checks passed
+pub fn check() -> anyhow::Result<bool> {
+    let config_files = AppConfig::find()?;
+    for config_file in &config_files {
+        println!("config:   {}", config_file.display());
+    }
+    let config = AppConfig::load_unlinted(&config_files)?;
+    let mut passed = true;
+
+    // Reported together, instead of being logged as they're found
+    let mut lints = config.lints();
+    lints.sort_by_key(|lint| std::cmp::Reverse(lint.severity));
+    let count = |severity| {
+        let count = lints
+            .iter()
+            .filter(|lint| lint.severity == severity)
+            .count();
+        format!("{count} {severity}{}", if count == 1 { "" } else { "s" })
+    };
+    if lints.is_empty() {
+        println!("lints:    none");
+    } else {
+        println!(
+            "lints:    {}, {}",
+            count(Severity::Error),
+            count(Severity::Warning),
+        );
+    }
+    let color = *COLOR && io::stdout().is_terminal();
+    for lint in &lints {
+        passed &= lint.severity != Severity::Error;
+        let label = format!("{:<7}", lint.severity);
+        let label = match lint.severity {
+            _ if !color => label,
+            Severity::Warning => format!("\x1b[33m{label}\x1b[0m"),
+            Severity::Error => format!("\x1b[31m{label}\x1b[0m"),
+        };
+        println!("  {label} {}", lint.message);
+    }
+
+    let root = config.get_root().ok();
+    match &root {
+        Some(root) => println!("root:     {}", root.display()),
+        None => {
+            passed = false;
+            println!("root:     not found");
+        },
+    }
+    // Without a root, report on why CWD wasn't used
+    let dir = root.as_deref().unwrap_or(&CWD);
+    for file in &config.required_files {
+        let found = file.exists_in(dir, config.file_checks());
+        print!(
+            "  {} {}",
+            if found { "found  " } else { "missing" },
+            file.path.display(),
+        );
+        match &file.hint {
+            Some(hint) if !found => println!(" ({hint})"),
+            _ => println!(),
+        }
+    }
+    let missing_extensions = config.missing_extensions_in(dir);
+    for extension in &config.required_extensions {
+        let extension = extension.trim_start_matches('.');
+        println!(
+            "  {} **/*.{extension}",
+            if missing_extensions.contains(&extension) {
+                "missing"
+            } else {
+                "found  "
+            },
+        );
+    }
+
+    let pinned = match &config.run.tool_versions {
+        Some(tool_versions) => match tool_versions.path_in(dir) {
+            Ok(pinned) => pinned.map(Run::Executable),
+            Err(why) => {
+                println!("run:      {why:#}");
+                return Ok(false);
+            },
+        },
+        None => None,
+    };
+    // Each dispatch target is checked, labelled by the argument that runs it
+    let targets = match (&pinned, &config.run.target) {
+        (Some(pinned), _) => vec![(None, pinned)],
+        (None, Run::Dispatch(dispatch)) => dispatch
+            .routes
+            .iter()
+            .map(|(route, target)| (Some(route.as_str()), target))
+            .chain(
+                dispatch
+                    .default
+                    .as_deref()
+                    .map(|default| (Some("default"), default)),
+            )
+            .collect(),
+        (None, target) => vec![(None, target)],
+    };
+    for (route, target) in targets {
+        let label = route.map_or(String::new(), |route| format!("{route}: "));
+        let command = match config.generate_run_target(target, dir, &[]) {
+            Ok(command) => command,
+            Err(why) => {
+                passed = false;
+                println!("run:      {label}{why:#}");
+                continue;
+            },
+        };
+        let program = Path::new(command.command.get_program());
+        match find_program(program, dir) {
+            Some(path) if is_executable(&path) => {
+                println!("run:      {label}{}", path.display());
+            },
+            Some(path) => {
+                passed = false;
+                println!(
+                    "run:      {label}{} (not executable)",
+                    path.display()
+                );
+            },
+            None => {
+                passed = false;
+                println!("run:      {label}{} (not found)", program.display());
+            },
+        }
+    }
+    Ok(passed)
+}
+
+/// A get-it-going config file
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    #[serde(default)]
+    required_files: Vec<RequiredFile>,
+    #[serde(default)]
+    required_files_mode: RequiredFilesMode,
+    /// File extensions that at least one file somewhere under the root must
+    /// have, each
+    #[serde(default)]
+    required_extensions: Vec<String>,
+    #[serde(default = "default_required_extensions_depth")]
+    required_extensions_depth: usize,
+    /// Directory names not searched for `required_extensions`, on top of
+    /// hidden ones and [`SKIPPED_CHILD_DIRECTORIES`]
+    #[serde(default)]
+    required_extensions_ignore: Vec<String>,
+    #[serde(default)]
+    case_insensitive_files: bool,
+    #[serde(default)]
+    check_readable: bool,
+    #[serde(default)]
+    search_parents: bool,
+    /// How long to keep looking for the root before giving up, in case the
+    /// required files are about to be created
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    wait_for_files: Option<Duration>,
+    #[serde(default)]
+    max_parent_depth: Option<usize>,
+    #[serde(default)]
+    search_boundary: Option<PathBuf>,
+    #[serde(default)]
+    search_children_depth: Option<usize>,
+    #[serde(default)]
+    root_markers: Vec<PathBuf>,
+    #[serde(default)]
+    root_offset: Option<PathBuf>,
+    #[serde(default)]
+    paths_relative_to: PathsRelativeTo,
+    #[serde(default)]
+    cache_root: bool,
+    #[serde(default)]
+    canonicalize_root: bool,
+    #[serde(default)]
+    env: EnvTable,
+    #[serde(default)]
+    env_passthrough: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_dotenv")]
+    load_dotenv: Option<Dotenv>,
+    #[serde(default = "default_root_env_var")]
+    root_env_var: String,
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    before_run: Vec<BeforeRun>,
+    run: RunConfig,
+    /// Run after `run`, however it went. These are the same as `before_run`
+    /// steps, minus the options that don't apply to them
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    after_run: Vec<BeforeRun>,
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    fallback: Vec<Fallback>,
+    #[serde(default)]
+    fallback_on_exit_codes: Vec<i32>,
+    #[serde(default)]
+    echo_command: bool,
+    #[serde(default)]
+    deny_elevated: bool,
+    #[serde(default, deserialize_with = "deserialize_exit_code_map")]
+    exit_code_map: BTreeMap<i32, i32>,
+    /// The config file this was loaded from
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl AppConfig {
+    /// Maps the exit code of the program run to the one gig should exit with,
+    /// according to `exit_code_map`
+    pub fn map_exit_code(&self, code: i32) -> i32 {
+        match self.exit_code_map.get(&code) {
+            Some(&mapped) => {
+                debug!("exit code {code} mapped to {mapped}");
+                mapped
+            },
+            None => code,
+        }
+    }
+
+    /// Finds the config file for [`NAME`] and loads it. With [`CONFIG_MERGE`],
+    /// every config file found is loaded and merged instead
+    pub fn find_and_load() -> anyhow::Result<Self> {
+        AppConfig::load_from_paths(&AppConfig::find()?)
+    }
+
+    /// Finds the config files to use, highest precedence first. There's only
+    /// more than one with [`CONFIG_MERGE`]
+    fn find() -> anyhow::Result<Vec<PathBuf>> {
+        if let Some(config_file) = env::var_os("GIG_CONFIG") {
+            let config_file = PathBuf::from(config_file);
+            if config_file == Path::new(STDIN_CONFIG) {
+                info!("reading config from stdin (GIG_CONFIG is -)");
+                return Ok(vec![config_file]);
+            }
+            if !config_file.is_file() {
+                bail!(
+                    "GIG_CONFIG is set to {}, which doesn't exist",
+                    config_file.display(),
+                );
+            }
+            info!("using {} (from GIG_CONFIG)", config_file.display());
+            return Ok(vec![config_file]);
+        }
+
+        let search_dirs = AppConfig::search_dirs();
+        if *CONFIG_MERGE {
+            let config_files = AppConfig::find_all_in(&search_dirs);
+            if !config_files.is_empty() {
+                return Ok(config_files);
+            }
+        }
+        // Also gives the error if there aren't any
+        Ok(vec![AppConfig::find_in(&search_dirs)?])
+    }
+
+    /// The directories searched for config files, in order
+    fn search_dirs() -> Vec<&'static Path> {
+        if let Some(dirs) = &*CONFIG_SEARCH_PATH {
+            debug!("GIG_CONFIG_PATH is set, only searching its directories");
+            return dirs.iter().map(PathBuf::as_path).collect();
+        }
+        // Lets locked down environments avoid configs they didn't write
+        let system_wide_directory = if env::var_os("GIG_NO_SYSTEM_CONFIG")
+            .is_some()
+        {
+            debug!("GIG_NO_SYSTEM_CONFIG is set, ignoring system-wide config");
+            None
+        } else {
+            Some(*SYSTEM_WIDE_CONFIG_DIRECTORY)
+        };
+        [
+            Some(CWD.as_path()),
+            USER_CONFIG_DIRECTORY.as_deref(),
+            system_wide_directory,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Finds the config file for [`NAME`] in the first of `search_dirs` that
+    /// has one. If a directory has config files in multiple formats, the
+    /// first of [`CONFIG_EXTENSIONS`] is used
+    pub fn find_in(search_dirs: &[&Path]) -> anyhow::Result<PathBuf> {
+        match search_dirs.iter().find_map(|dir| config_file_in(dir)) {
+            Some(path) => Ok(path),
+            None => {
+                let suggestions = suggest_config_files(search_dirs, &NAME);
+                match suggestions.as_slice() {
+                    [] => bail!("unable to find config file"),
+                    [(path, name)] => bail!(
+                        "unable to find config file for {}, did you mean {}? \
+                         Set GIG_OVERRIDE={name} to use it",
+                        &*NAME,
+                        path.display(),
+                    ),
+                    suggestions => bail!(
+                        "unable to find config file for {}, did you mean one \
+                         of {}? Set GIG_OVERRIDE to its name to use it",
+                        &*NAME,
+                        suggestions
+                            .iter()
+                            .map(|(path, _)| path.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    ),
+                }
+            },
+        }
+    }
+
+    /// Finds the config file for [`NAME`] in each of `search_dirs` that has
+    /// one, in the same order
+    pub fn find_all_in(search_dirs: &[&Path]) -> Vec<PathBuf> {
+        search_dirs
+            .iter()
+            .filter_map(|dir| config_file_in(dir))
+            .collect()
+    }
+
+    pub fn load_from_path(config_file: &Path) -> anyhow::Result<Self> {
+        AppConfig::load_from_paths(&[config_file.to_owned()])
+    }
+
+    /// Loads `config_files` merged together, with each overriding the ones
+    /// after it. Relative paths in each are still resolved against its own
+    /// directory, but `paths_relative_to = "config"` uses the first one's
+    pub fn load_from_paths(config_files: &[PathBuf]) -> anyhow::Result<Self> {
+        let config = AppConfig::load_unlinted(config_files)?;
+        config.lint().with_context(|| {
+            format!("invalid config {}", joined(config_files))
+        })?;
+        Ok(config)
+    }
+
+    /// Loads and merges `config_files`, without checking the result
+    fn load_unlinted(config_files: &[PathBuf]) -> anyhow::Result<Self> {
+        let config = merged_table(config_files)?;
+        let mut config = toml::Value::Table(config)
+            .try_into::<AppConfig>()
+            .map_err(|why| locate_error(config_files, why))?;
+        config.path = config_files[0].to_owned();
+        Ok(config)
+    }
+
+    fn get_root(&self) -> anyhow::Result<Cow<'_, Path>> {
+        let Some(timeout) = self.wait_for_files else {
+            return self.resolve_root_in(&CWD);
+        };
+        let deadline = Instant::now() + timeout;
+        let mut waited = false;
+        loop {
+            match self.resolve_root_in(&CWD) {
+                Err(why)
+                    if why.is::<RootNotFound>()
+                        && Instant::now() < deadline => {},
+                result => return result,
+            }
+            if !waited {
+                info!(
+                    "required files not found, waiting up to {}",
+                    humantime::format_duration(timeout),
+                );
+                waited = true;
+            }
+            thread::sleep(WAIT_FOR_FILES_INTERVAL);
+        }
+    }
+
+    /// Finds the root directory as if get-it-going was run from `cwd`. If the
+    /// required files couldn't be found, the error is a [`RootNotFound`]
+    /// saying which
+    pub fn resolve_root_in<'a>(
+        &self,
+        cwd: &'a Path,
+    ) -> anyhow::Result<Cow<'a, Path>> {
+        let found = self.find_root_in(cwd)?;
+        self.apply_root_offset(found)
+    }
+
+    /// Moves the `found` root by `root_offset`, if there is one. The offset
+    /// root must be a directory, and can't be above the filesystem's root
+    fn apply_root_offset<'a>(
+        &self,
+        found: Cow<'a, Path>,
+    ) -> anyhow::Result<Cow<'a, Path>> {
+        let Some(offset) = &self.root_offset else {
+            return Ok(found);
+        };
+        let mut root = found.to_path_buf();
+        for component in offset.components() {
+            match component {
+                Component::ParentDir => {
+                    if !root.pop() {
+                        bail!(
+                            "root_offset {} goes above {}",
+                            offset.display(),
+                            root.display(),
+                        );
+                    }
+                },
+                Component::Normal(name) => root.push(name),
+                Component::CurDir => {},
+                Component::RootDir | Component::Prefix(_) => {
+                    bail!(
+                        "root_offset must be relative, but is {}",
+                        offset.display()
+                    )
+                },
+            }
+        }
+        if !root.is_dir() {
+            bail!(
+                "{} (from root_offset {}) isn't a directory",
+                root.display(),
+                offset.display(),
+            );
+        }
+        info!(
+            "using {} as the root ({} offset by {})",
+            root.display(),
+            found.display(),
+            offset.display(),
+        );
+        Ok(root.into())
+    }
+
+    /// Finds the directory the required files are in, before `root_offset` is
+    /// applied
+    fn find_root_in<'a>(
+        &self,
+        cwd: &'a Path,
+    ) -> Result<Cow<'a, Path>, RootNotFound> {
+        if !self.root_markers.is_empty() {
+            return self.resolve_root_by_markers(cwd);
+        }
+        let searched_up_to = if !self.has_requirements() {
+            return Ok(cwd.into());
+        } else if self.search_parents {
+            if let Some(root) = self.read_cached_root(cwd) {
+                return Ok(root.into());
+            }
+            match self.search_parents_for_root(cwd) {
+                Ok(root) => {
+                    self.write_cached_root(cwd, &root);
+                    return Ok(root);
+                },
+                Err(dir) => dir,
+            }
+        } else if self.required_files_exist_in(cwd) {
+            return Ok(cwd.into());
+        } else {
+            cwd
+        };
+        if let Some(root) = self.search_children_for_root(cwd) {
+            return Ok(root.into());
+        }
+        // Files missing from the parents are just noise, as they'd likely be
+        // missing from every parent
+        Err(self.not_found_in(cwd, searched_up_to))
+    }
+
+    /// Why `dir` isn't the root, having searched up to `searched_up_to`
+    fn not_found_in(&self, dir: &Path, searched_up_to: &Path) -> RootNotFound {
+        RootNotFound {
+            missing: self.missing_in(dir),
+            hints: self
+                .missing_files_in(dir)
+                .filter_map(|file| {
+                    Some((file.path.clone(), file.hint.clone()?))
+                })
+                .collect(),
+            cwd: dir.to_owned(),
+            searched_up_to: searched_up_to.to_owned(),
+        }
+    }
+
+    /// Finds the root as the nearest of `cwd` and its parents containing any
+    /// of the `root_markers`. The required files then only need to be in that
+    /// directory
+    fn resolve_root_by_markers<'a>(
+        &self,
+        cwd: &'a Path,
+    ) -> Result<Cow<'a, Path>, RootNotFound> {
+        match self.search_parents_for_marker(cwd) {
+            Ok(root)
+                if !self.has_requirements()
+                    || self.required_files_exist_in(root) =>
+            {
+                Ok(root.into())
+            },
+            Ok(root) => Err(self.not_found_in(root, root)),
+            Err(searched_up_to) => Err(RootNotFound {
+                missing: self.root_markers.clone(),
+                hints: Vec::new(),
+                cwd: cwd.to_owned(),
+                searched_up_to: searched_up_to.to_owned(),
+            }),
+        }
+    }
+
+    /// Searches `cwd` and its parents for the nearest directory containing any
+    /// of the `root_markers`. If there isn't one, returns the last directory
+    /// that was searched
+    fn search_parents_for_marker<'a>(
+        &self,
+        cwd: &'a Path,
+    ) -> Result<&'a Path, &'a Path> {
+        let mut searched_up_to = cwd;
+        for (depth, dir) in cwd.ancestors().enumerate() {
+            if self.max_parent_depth.is_some_and(|max| depth > max) {
+                debug!("reached max_parent_depth without finding root_markers");
+                break;
+            }
+            searched_up_to = dir;
+            let marker = self
+                .root_markers
+                .iter()
+                .find(|marker| dir.join(marker).exists());
+            if let Some(marker) = marker {
+                debug!("found {} in {}", marker.display(), dir.display());
+                return Ok(dir);
+            }
+        }
+        Err(searched_up_to)
+    }
+
+    fn file_checks(&self) -> FileChecks {
+        FileChecks {
+            case_insensitive: self.case_insensitive_files,
+            readable: self.check_readable,
+        }
+    }
+
+    /// Whether there's anything that has to be found for a directory to be
+    /// the root
+    fn has_requirements(&self) -> bool {
+        !self.required_files.is_empty() || !self.required_extensions.is_empty()
+    }
+
+    /// Whether `dir` has the required files, and files with each of the
+    /// required extensions
+    fn required_files_exist_in(&self, dir: &Path) -> bool {
+        let mut files = self.required_files.iter();
+        let exists =
+            |file: &RequiredFile| file.exists_in(dir, self.file_checks());
+        let files_exist = match self.required_files_mode {
+            // Any of no files can't exist, but there may only be extensions
+            RequiredFilesMode::Any if self.required_files.is_empty() => true,
+            RequiredFilesMode::All => files.all(exists),
+            RequiredFilesMode::Any => files.any(exists),
+        };
+        files_exist && self.missing_extensions_in(dir).is_empty()
+    }
+
+    /// The required files that can't be found in `dir`
+    fn missing_in(&self, dir: &Path) -> Vec<PathBuf> {
+        let files = self.missing_files_in(dir).map(|file| file.path.clone());
+        let extensions = self
+            .missing_extensions_in(dir)
+            .into_iter()
+            .map(|extension| PathBuf::from(format!("**/*.{extension}")));
+        files.chain(extensions).collect()
+    }
+
+    /// The `required_extensions` that no file under `dir` has, searching
+    /// breadth-first up to `required_extensions_depth` levels down. Stops as
+    /// soon as every extension has been found
+    fn missing_extensions_in(&self, dir: &Path) -> Vec<&str> {
+        let mut missing = self
+            .required_extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.'))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return missing;
+        }
+        let mut queue = VecDeque::from([(dir.to_owned(), 0)]);
+        while let Some((dir, depth)) = queue.pop_front() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(why) => {
+                    debug!("couldn't read {}: {why}", dir.display());
+                    continue;
+                },
+            };
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if file_type.is_dir() {
+                    if depth < self.required_extensions_depth
+                        && !name.starts_with('.')
+                        && !SKIPPED_CHILD_DIRECTORIES.contains(&name.as_ref())
+                        && !self
+                            .required_extensions_ignore
+                            .iter()
+                            .any(|ignored| *ignored == name)
+                    {
+                        queue.push_back((entry.path(), depth + 1));
+                    }
+                    continue;
+                }
+                let Some((_, extension)) = name.rsplit_once('.') else {
+                    continue;
+                };
+                missing.retain(|wanted| {
+                    let found = if self.case_insensitive_files {
+                        wanted.eq_ignore_ascii_case(extension)
+                    } else {
+                        *wanted == extension
+                    };
+                    if found {
+                        trace!(
+                            "found .{wanted} file {}",
+                            entry.path().display()
+                        );
+                    }
+                    !found
+                });
+                if missing.is_empty() {
+                    return missing;
+                }
+            }
+        }
+        missing
+    }
+
+    /// The required files that can't be found in `dir`
+    fn missing_files_in<'a>(
+        &'a self,
+        dir: &'a Path,
+    ) -> impl Iterator<Item = &'a RequiredFile> + 'a {
+        self.required_files
+            .iter()
+            .filter(move |file| !file.exists_in(dir, self.file_checks()))
+    }
+
+    fn at_search_boundary(&self, dir: &Path) -> bool {
+        let Some(marker) = &self.search_boundary else {
+            return false;
+        };
+        let at_boundary = dir.join(marker).exists();
+        if at_boundary {
+            debug!(
+                "found search_boundary in {}, not searching any further",
+                dir.display(),
+            );
+        }
+        at_boundary
+    }
+
+    /// Searches `cwd` and its parents for the root. If it can't be found,
+    /// returns the last directory that was searched
+    fn search_parents_for_root<'a>(
+        &self,
+        cwd: &'a Path,
+    ) -> Result<Cow<'a, Path>, &'a Path> {
+        let mut dir = cwd;
+        if self.required_files_exist_in(dir) {
+            return Ok(dir.into());
+        } else if self.at_search_boundary(dir) {
+            return Err(dir);
+        }
+        let mut depth = 0;
+        // Can't use while-let with break values, so we overcome
+        loop {
+            if self.max_parent_depth.is_some_and(|max| depth >= max) {
+                debug!(
+                    "reached max_parent_depth ({depth}) without finding \
+                     required files"
+                );
+                break Err(dir);
+            }
+            depth += 1;
+            match dir.parent() {
+                Some(dir) if self.required_files_exist_in(dir) => {
+                    break Ok(dir.into());
+                },
+                Some(new_dir) if self.at_search_boundary(new_dir) => {
+                    break Err(new_dir)
+                },
+                Some(new_dir) => dir = new_dir,
+                None => break Err(dir),
+            }
+        }
+    }
+
+    /// Searches breadth-first through the subdirectories of `cwd`, up to
+    /// `search_children_depth` levels down, for the root. Hidden directories
+    /// and ones known to hold build outputs or dependencies are skipped
+    fn search_children_for_root(&self, cwd: &Path) -> Option<PathBuf> {
+        let max_depth = self.search_children_depth?;
+        let mut queue = VecDeque::from([(cwd.to_owned(), 0)]);
+        while let Some((dir, depth)) = queue.pop_front() {
+            if depth >= max_depth {
+                continue;
+            }
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(why) => {
+                    debug!("couldn't read {}: {why}", dir.display());
+                    continue;
+                },
+            };
+            let mut children = entries
+                .flatten()
+                .filter(|entry| {
+                    entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+                })
+                .filter(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    !name.starts_with('.')
+                        && !SKIPPED_CHILD_DIRECTORIES.contains(&name.as_ref())
+                })
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>();
+            // Directory order isn't consistent between platforms
+            children.sort();
+            for child in children {
+                debug!("searching child directory {}", child.display());
+                if self.required_files_exist_in(&child) {
+                    return Some(child);
+                }
+                queue.push_back((child, depth + 1));
+            }
+        }
+        None
+    }
+
+    /// Where the root found for `cwd` would be cached, if
+    /// `cache_root` is enabled and the cache can be relied upon for this
+    /// config. Also returns a fingerprint of the config options that affect
+    /// which directory is the root
+    fn root_cache_file(&self, cwd: &Path) -> Option<(PathBuf, u64)> {
+        if !self.cache_root {
+            return None;
+        }
+        // The cache is invalidated by the modification time of the directories
+        // between cwd and the root changing, which only happens if their
+        // direct contents change. Nested paths could start matching without
+        // that, so can't be cached safely
+        let nested_boundary = self
+            .search_boundary
+            .as_ref()
+            .is_some_and(|marker| marker.components().count() > 1);
+        if nested_boundary
+            || !self.required_extensions.is_empty()
+            || self
+                .required_files
+                .iter()
+                .any(|file| file.path.components().count() > 1)
+        {
+            debug!("not using root cache, config has nested paths");
+            return None;
+        }
+        // Likewise, editing a file doesn't change its directory's modification
+        // time
+        if self
+            .required_files
+            .iter()
+            .any(|file| file.content.is_some())
+        {
+            debug!("not using root cache, config checks file content");
+            return None;
+        }
+        // Nor does changing a file's permissions
+        if self.check_readable {
+            debug!("not using root cache, config checks files are readable");
+            return None;
+        }
+        let cache_dir = dirs::cache_dir()?.join("get-it-going").join("roots");
+        let mut hasher = DefaultHasher::new();
+        cwd.hash(&mut hasher);
+        let file_name = format!("{}-{:016x}", NAME.as_ref(), hasher.finish());
+
+        let mut hasher = DefaultHasher::new();
+        format!(
+            "{:?} {:?} {:?} {:?} {:?}",
+            self.required_files,
+            self.required_files_mode,
+            self.max_parent_depth,
+            self.search_boundary,
+            self.case_insensitive_files,
+        )
+        .hash(&mut hasher);
+        Some((cache_dir.join(file_name), hasher.finish()))
+    }
+
+    /// Gets the cached root for `cwd`, so long as it can still be trusted to
+    /// be correct
+    fn read_cached_root(&self, cwd: &Path) -> Option<PathBuf> {
+        let (cache_file, fingerprint) = self.root_cache_file(cwd)?;
+        let cache = fs::read_to_string(&cache_file).ok()?;
+        let mut lines = cache.lines();
+        if lines.next()? != format!("{fingerprint:016x}")
+            || Path::new(lines.next()?) != cwd
+        {
+            debug!("root cache is for a different config or CWD, ignoring");
+            return None;
+        }
+        let root = PathBuf::from(lines.next()?);
+        if !cwd.starts_with(&root) {
+            return None;
+        }
+        for line in lines {
+            let (mtime, dir) = line.split_once(' ')?;
+            if dir_mtime(Path::new(dir))?.as_nanos().to_string() != mtime {
+                debug!(
+                    "{dir} has changed since root was cached, ignoring cache"
+                );
+                return None;
+            }
+        }
+        if !self.required_files_exist_in(&root) {
+            debug!("cached root no longer has required files, ignoring cache");
+            return None;
+        }
+        debug!("using cached root {}", root.display());
+        Some(root)
+    }
+
+    /// Caches `root` as the root for `cwd`. Errors are ignored, as not having a
+    /// cache isn't a problem
+    fn write_cached_root(&self, cwd: &Path, root: &Path) {
+        let Some((cache_file, fingerprint)) = self.root_cache_file(cwd) else {
+            return;
+        };
+        let (Some(cwd_str), Some(root_str)) = (cwd.to_str(), root.to_str())
+        else {
+            debug!("not caching root, paths aren't UTF-8");
+            return;
+        };
+        let mut cache = format!("{fingerprint:016x}\n{cwd_str}\n{root_str}\n");
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        for dir in cwd.ancestors() {
+            let Some(mtime) = dir_mtime(dir) else {
+                return;
+            };
+            // Filesystems can have coarse timestamps, so a directory modified
+            // just now could be modified again without its mtime changing
+            if now.saturating_sub(mtime) < Duration::from_secs(2) {
+                debug!(
+                    "not caching root, {} was modified too recently",
+                    dir.display(),
+                );
+                return;
+            }
+            // dir must be UTF-8 given it's an ancestor of cwd
+            cache.push_str(&format!(
+                "{} {}\n",
+                mtime.as_nanos(),
+                dir.to_str().unwrap(),
+            ));
+            if dir == root {
+                break;
+            }
+        }
+        let write = fs::create_dir_all(cache_file.parent().unwrap())
+            .and_then(|_| fs::write(&cache_file, cache));
+        match write {
+            Ok(()) => debug!("cached root in {}", cache_file.display()),
+            Err(why) => debug!("failed to cache root: {why}"),
+        }
+    }
+
+    /// Runs the `via` resolver in `root` to find the program to run, or gets
+    /// the path it found before
+    fn resolve_via<'a>(
+        &self,
+        via: &'a Via,
+        root: &Path,
+    ) -> anyhow::Result<&'a Path> {
+        let program = via.resolved.get_or_try_init(|| {
+            let mut iter = Shlex::new(&via.resolver);
+            let Some(resolver) = iter.next() else {
+                bail!("via resolver can't be empty");
+            };
+            let mut command = Command::new(resolver);
+            command.args(iter);
+            self.apply_env(&mut command, Some(root), false)?;
+            command.current_dir(root);
+            command.stderr(Stdio::inherit());
+            // The resolver could be (or run) a gig shim too
+            set_depth(&mut command);
+            // The resolver is run even during a dry run, as otherwise there's
+            // no way to know what would be run
+            debug!("running via resolver `{}`", via.resolver);
+            let output = command.output().with_context(|| {
+                format!("failed to run via resolver `{}`", via.resolver)
+            })?;
+            if !output.status.success() {
+                bail!(
+                    "via resolver `{}` returned a non-zero status",
+                    via.resolver,
+                );
+            }
+            let stdout =
+                String::from_utf8(output.stdout).with_context(|| {
+                    format!(
+                        "via resolver `{}` output isn't UTF-8",
+                        via.resolver
+                    )
+                })?;
+            let program = stdout.trim();
+            if program.is_empty() {
+                bail!("via resolver `{}` didn't output a path", via.resolver);
+            }
+            info!("via resolver chose {program}");
+            Ok(PathBuf::from(program))
+        })?;
+        Ok(program)
+    }
+
+    /// Sets the top-level `env` variables for `command`, as well as
+    /// `root_env_var` if there's a `root`. With `env_passthrough`, only the
+    /// variables it lists are inherited, unless `env_cleared` means none are.
+    /// Any command-specific variables should be set afterwards, so they take
+    /// precedence
+    fn apply_env(
+        &self,
+        command: &mut Command,
+        root: Option<&Path>,
+        env_cleared: bool,
+    ) -> anyhow::Result<()> {
+        if let Some(passthrough) =
+            self.env_passthrough.as_ref().filter(|_| !env_cleared)
+        {
+            // Anything already set for the command (e.g. from load_dotenv) is
+            // kept
+            let set = command
+                .get_envs()
+                .filter_map(|(name, value)| {
+                    Some((name.to_owned(), value?.to_owned()))
+                })
+                .collect::<Vec<_>>();
+            command.env_clear();
+            for name in passthrough {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+            command.envs(set);
+        }
+        if let Some(root) = root.filter(|_| !self.root_env_var.is_empty()) {
+            command.env(&self.root_env_var, root);
+        }
+        self.env.apply(command, root)
+    }
+
+    /// Whether commands inherit gig's `name` variable (when they haven't
+    /// cleared their environment), and it's set
+    fn inherits(&self, name: &str) -> bool {
+        let passed_through =
+            self.env_passthrough.as_ref().is_none_or(|passthrough| {
+                passthrough.iter().any(|var| var == name)
+            });
+        passed_through && env::var_os(name).is_some()
+    }
+
+    /// The directory relative `run` and `script_path` paths are relative to
+    fn paths_base<'a>(&'a self, root: &'a Path) -> Cow<'a, Path> {
+        match self.paths_relative_to {
+            PathsRelativeTo::Root => root.into(),
+            PathsRelativeTo::Config => {
+                let config_dir = self.path.parent().unwrap_or(Path::new(""));
+                // GIG_CONFIG could be relative
+                if config_dir.is_absolute() {
+                    config_dir.into()
+                } else {
+                    CWD.join(config_dir).into()
+                }
+            },
+        }
+    }
+
+    fn generate_before_run(
+        &self,
+        before_run: &BeforeRun,
+        root: &Path,
+    ) -> anyhow::Result<LoggedCommand> {
+        let working_directory =
+            working_directory(root, before_run.working_directory.as_deref())?;
+        let mut command = match &before_run.task {
+            Task::Command(cmd_str) => {
+                command_from_str(cmd_str, before_run.shell)
+            },
+            Task::ScriptPath(path) => {
+                let base = self.paths_base(root);
+                let full_path = base.join(path);
+                if !full_path.is_file() {
+                    bail!(
+                        "before_run script {} isn't a file",
+                        full_path.display(),
+                    );
+                }
+                // Unlike bare program names, scripts are never looked for in
+                // $PATH, so they always need to be relative to the base
+                let script: Cow<Path> = if base != working_directory {
+                    full_path.as_path().into()
+                } else if path.components().count() == 1 {
+                    Path::new(".").join(path).into()
+                } else {
+                    path.into()
+                };
+                let interpreter = match &before_run.interpreter {
+                    Some(interpreter) => {
+                        Some(Shlex::new(interpreter).collect())
+                    },
+                    None => interpreter_for(&full_path),
+                };
+                if interpreter.is_none() && !is_executable(&full_path) {
+                    bail!(
+                        "before_run script {} isn't executable, make it \
+                         executable or set an interpreter",
+                        full_path.display(),
+                    );
+                }
+                match interpreter.as_deref() {
+                    Some([program, args @ ..]) => {
+                        let mut command = Command::new(program);
+                        command.args(args);
+                        command.arg(script.as_os_str());
+                        command
+                    },
+                    _ => Command::new(script.as_os_str()),
+                }
+            },
+        };
+        if before_run.clear_env {
+            clear_env(&mut command);
+        }
+        self.apply_env(&mut command, Some(root), before_run.clear_env)?;
+        before_run.env.apply(&mut command, Some(root))?;
+        command.current_dir(working_directory);
+        Ok(LoggedCommand {
+            resolution: Resolution::for_dry_run(&command),
+            redacted_env: Vec::new(),
+            command,
+            timeout: before_run.timeout,
+            stdin: InputMode::Inherit,
+            stdout: if before_run.capture_into.is_some() {
+                OutputMode::Capture
+            } else {
+                before_run.stdout
+            },
+            stderr: before_run.stderr,
+        })
+    }
+
+    /// Makes the command for running whatever `args` would run, as
+    /// [`launch`] does in steps
+    #[cfg(test)]
+    fn generate_run(
+        &self,
+        root: &Path,
+        args: &[OsString],
+    ) -> anyhow::Result<LoggedCommand> {
+        let (target, args) = self.run_target(root, args)?;
+        self.generate_run_target(&target, root, args)
+    }
+
+    /// The target to run for `args` (see [`Run::dispatch`]), and the arguments
+    /// to pass on to it. This is the version pinned by `run.tool_versions`
+    /// instead, if there is one
+    fn run_target<'a>(
+        &'a self,
+        root: &Path,
+        args: &'a [OsString],
+    ) -> anyhow::Result<(Cow<'a, Run>, &'a [OsString])> {
+        let (target, args) = self.run.target.dispatch(args)?;
+        if let Some(tool_versions) = &self.run.tool_versions {
+            if let Some(path) = tool_versions.path_in(root)? {
+                return Ok((Cow::Owned(Run::Executable(path)), args));
+            }
+        }
+        Ok((Cow::Borrowed(target), args))
+    }
+
+    /// Makes the command for running `target`, which mustn't be a
+    /// [`Run::Dispatch`]
+    fn generate_run_target(
+        &self,
+        target: &Run,
+        root: &Path,
+        args: &[OsString],
+    ) -> anyhow::Result<LoggedCommand> {
+        let working_directory =
+            working_directory(root, self.run.working_directory.as_deref())?;
+        let base = self.paths_base(root);
+        let program: Cow<Path> = match target {
+            Run::Shell(_) => Path::new("").into(),
+            Run::SubcommandOf(this) => Path::new(&this.program).into(),
+            Run::PrependFolder(folder) => folder.join(NAME.as_ref()).into(),
+            Run::Executable(this) => this.into(),
+            Run::Via(via) => self.resolve_via(via, root)?.into(),
+            Run::Dispatch(_) => unreachable!("dispatch targets can't dispatch"),
+        };
+        let program = relative_to(&program, &base, &working_directory);
+        // Windows only finds .exe files by itself, but some tools (e.g. npm)
+        // are .cmd or .bat shims instead, so extensions are tried like cmd does
+        let program: Cow<Path> = match target {
+            Run::Executable(_) | Run::PrependFolder(_)
+                if cfg!(windows) && program.extension().is_none() =>
+            {
+                let found = if program.components().count() == 1 {
+                    env::var_os("PATH").and_then(|path| which(&program, &path))
+                } else {
+                    with_pathext(&program, &working_directory, &pathext())
+                };
+                match found {
+                    Some(found) => found.into(),
+                    // Still give a useful name if it can't be found
+                    None if matches!(target, Run::PrependFolder(_)) => {
+                        program.with_extension("exe").into()
+                    },
+                    None => program,
+                }
+            },
+            _ => program,
+        };
+
+        let args = args
+            .iter()
+            .map(OsString::as_os_str)
+            .filter(|arg| {
+                let keep = self.run.args_filter.keeps(arg);
+                if !keep {
+                    debug!("not passing on {} (args_filter)", arg.display());
+                }
+                keep
+            })
+            .collect::<Vec<_>>();
+        let mut run_args = Vec::<&OsStr>::new();
+        if let Run::SubcommandOf(this) = target {
+            run_args.push(
+                this.subcommand.as_deref().unwrap_or(NAME.as_ref()).as_ref(),
+            );
+        }
+        run_args.extend(self.run.args_prepend.iter().map(OsStr::new));
+        match &self.run.args_template {
+            Some(template) => {
+                for arg in template {
+                    if is_args_placeholder(arg) {
+                        run_args.extend(&args);
+                    } else {
+                        run_args.push(arg.as_ref());
+                    }
+                }
+            },
+            None => run_args.extend(&args),
+        }
+        run_args.extend(self.run.args_append.iter().map(OsStr::new));
+        let mut command = match target {
+            Run::Shell(line) => shell_command(line, &run_args),
+            _ => {
+                let mut command = Command::new(program.as_os_str());
+                command.args(run_args);
+                command
+            },
+        };
+        if self.run.clear_env {
+            clear_env(&mut command);
+        }
+        if let Some(dotenv) = &self.load_dotenv {
+            dotenv.apply(&mut command, root, |name| {
+                !self.run.clear_env && self.inherits(name)
+            })?;
+        }
+        self.apply_env(&mut command, Some(root), self.run.clear_env)?;
+        for name in &self.run.env_remove {
+            command.env_remove(name);
+        }
+        self.run.env.apply(&mut command, Some(root))?;
+        if let Some(nice) = self.run.nice() {
+            set_nice(&mut command, nice);
+        }
+        if let Some(umask) = self.run.umask {
+            set_umask(&mut command, umask);
+        }
+        match &self.run.arg0 {
+            // The shell would be renamed instead of the program
+            _ if matches!(target, Run::Shell(_)) => {},
+            Arg0::Program => {},
+            Arg0::Name => set_arg0(&mut command, NAME.as_ref()),
+            Arg0::Custom(arg0) => set_arg0(&mut command, arg0),
+        }
+        command.current_dir(working_directory);
+        Ok(LoggedCommand {
+            resolution: if *DRY_RUN {
+                self.run_resolution(target, &command)
+            } else {
+                Resolution::Unchecked
+            },
+            redacted_env: Vec::new(),
+            command,
+            timeout: self.run.timeout,
+            // Relative to the root, like the rest of run's paths
+            stdin: match &self.run.stdin {
+                InputMode::File(path) => InputMode::File(root.join(path)),
+                stdin => stdin.clone(),
+            },
+            stdout: OutputMode::Inherit,
+            stderr: OutputMode::Inherit,
+        })
+    }
+
+    /// Whether `command`, made for `target`, could be run. Relative paths could
+    /// be created by before_run, so aren't counted as missing if there are
+    /// before_run steps, like in [`AppConfig::lints`]
+    fn run_resolution(&self, target: &Run, command: &Command) -> Resolution {
+        let could_be_built = !self.before_run.is_empty()
+            && match target {
+                Run::Executable(path) => {
+                    path.is_relative() && path.components().count() > 1
+                },
+                Run::PrependFolder(folder) => folder.is_relative(),
+                _ => false,
+            };
+        match Resolution::of(command) {
+            Resolution::NotFound if could_be_built => Resolution::NotBuiltYet,
+            resolution => resolution,
+        }
+    }
+
+    /// Makes the command for each fallback. Fallbacks that can't be made are
+    /// errors, to let another fallback be tried instead
+    fn generate_fallback(
+        &self,
+        args: &[OsString],
+    ) -> Vec<anyhow::Result<LoggedCommand>> {
+        self.fallback
+            .iter()
+            .map(|fallback| {
+                let command = match &fallback.target {
+                    Some(FallbackTarget::Candidates(candidates)) => {
+                        // Skip gig's own directory, in case one of the
+                        // candidates is what gig is installed as
+                        let gig_path = env::current_exe()
+                            .context("can't find gig's own path")?;
+                        let gig_dir = gig_path.parent().with_context(|| {
+                            format!(
+                                "gig's own path {} has no parent directory",
+                                gig_path.display(),
+                            )
+                        })?;
+                        let path = env::var_os("PATH").unwrap_or_default();
+                        let path = remove_from_path(&path, gig_dir);
+                        let program = candidates
+                            .iter()
+                            .find_map(|candidate| {
+                                which(Path::new(candidate), &path)
+                            })
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "none of {} could be found in $PATH",
+                                    candidates.join(", "),
+                                )
+                            })?;
+                        let mut command = Command::new(program);
+                        command.args(args);
+                        self.apply_env(&mut command, None, false)?;
+                        command
+                    },
+                    Some(FallbackTarget::Path(path)) => {
+                        let mut command = Command::new(path);
+                        command.args(args);
+                        self.apply_env(&mut command, None, false)?;
+                        command
+                    },
+                    Some(FallbackTarget::Command(cmd_str)) => {
+                        let mut command =
+                            command_from_str(cmd_str, fallback.shell);
+                        self.apply_env(&mut command, None, false)?;
+                        command
+                    },
+                    None => {
+                        // Re-run command without GIG in $PATH
+                        let gig_path = env::current_exe().unwrap();
+                        let gig_dir = gig_path.parent().unwrap();
+                        let path = env::var_os("PATH").expect("$PATH unset");
+                        let new_path = remove_from_path(&path, gig_dir);
+                        trace!(
+                            "$PATH before:\n{}\n$PATH after:\n{}",
+                            path.to_string_lossy(),
+                            new_path.to_string_lossy(),
+                        );
+
+                        let mut command = Command::new(NAME.as_ref());
+                        command.args(args);
+                        self.apply_env(&mut command, None, false)?;
+                        // Overwrite $PATH with our edited one
+                        command.env("PATH", new_path);
+                        command
+                    },
+                };
+                Ok(LoggedCommand {
+                    resolution: Resolution::for_dry_run(&command),
+                    redacted_env: Vec::new(),
+                    command,
+                    timeout: None,
+                    stdin: InputMode::Inherit,
+                    stdout: OutputMode::Inherit,
+                    stderr: OutputMode::Inherit,
+                })
+            })
+            .collect()
+    }
+
+    /// Logs warnings about the config, and fails if there are any errors
+    fn lint(&self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+        for lint in self.lints() {
+            match lint.severity {
+                Severity::Warning => warn!("{}", lint.message),
+                Severity::Error => errors.push(lint.message),
+            }
+        }
+        match errors.as_slice() {
+            [] => Ok(()),
+            [error] => bail!("{error}"),
+            errors => {
+                bail!("{} errors:\n  {}", errors.len(), errors.join("\n  "))
+            },
+        }
+    }
+
+    /// Finds options that have no effect (warnings), and config that would
+    /// fail at launch (errors)
+    fn lints(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        macro_rules! warning {
+            ($($arg:tt)*) => {
+                lints.push(Lint {
+                    severity: Severity::Warning,
+                    message: format!($($arg)*),
+                })
+            };
+        }
+        macro_rules! error {
+            ($($arg:tt)*) => {
+                lints.push(Lint {
+                    severity: Severity::Error,
+                    message: format!($($arg)*),
+                })
+            };
+        }
+
+        if let Some(offset) = &self.root_offset {
+            // A drive-relative path like C:foo has a prefix without being
+            // absolute, but can't be applied to the root either
+            if offset.components().any(|component| {
+                matches!(component, Component::RootDir | Component::Prefix(_))
+            }) {
+                error!(
+                    "root_offset must be relative, but is {}",
+                    offset.display(),
+                );
+            }
+        }
+        for target in self.run.target.targets() {
+            match target {
+                Run::SubcommandOf(this) if this.program.is_empty() => {
+                    error!("run.subcommand_of can't be empty");
+                },
+                Run::Shell(line) if line.trim().is_empty() => {
+                    error!("run.shell can't be empty");
+                },
+                Run::Executable(path) if path.as_os_str().is_empty() => {
+                    error!("run.path can't be empty");
+                },
+                // Relative paths could be created by before_run, so can't be
+                // checked until then
+                Run::Executable(path)
+                    if self.fallback.is_empty()
+                        && (path.is_absolute()
+                            || path.components().count() == 1)
+                        && find_program(path, &CWD).is_none() =>
+                {
+                    error!(
+                        "run.path {} doesn't exist, and there's no fallback",
+                        path.display(),
+                    );
+                },
+                _ => {},
+            }
+        }
+        for (index, before_run) in self.before_run.iter().enumerate() {
+            if before_run.capture_into.is_some() {
+                if index + 1 != self.before_run.len() {
+                    error!(
+                        "{} has capture_into, but only the last before_run \
+                         step can",
+                        before_run.label(index),
+                    );
+                }
+                if before_run.stdout != OutputMode::Inherit {
+                    warning!(
+                        "{} stdout has no effect with capture_into",
+                        before_run.label(index),
+                    );
+                }
+            }
+            // Scripts relative to the root can't be found until it is, so
+            // generate_before_run checks they exist instead
+            let script = match &before_run.task {
+                Task::ScriptPath(path)
+                    if path.is_absolute()
+                        || matches!(
+                            self.paths_relative_to,
+                            PathsRelativeTo::Config
+                        ) =>
+                {
+                    Some(self.paths_base(Path::new("")).join(path))
+                },
+                _ => None,
+            };
+            if let Some(path) = &script {
+                if !path.is_file() {
+                    error!(
+                        "{} script {} isn't a file",
+                        before_run.label(index),
+                        path.display(),
+                    );
+                } else if before_run.interpreter.is_none()
+                    && !is_executable(path)
+                    && interpreter_for(path).is_none()
+                {
+                    error!(
+                        "{} script {} isn't executable, make it executable or \
+                         set an interpreter",
+                        before_run.label(index),
+                        path.display(),
+                    );
+                }
+            }
+        }
+
+        for (index, after_run) in self.after_run.iter().enumerate() {
+            for (option, set) in [
+                ("allow_failure", after_run.allow_failure),
+                ("parallel", after_run.parallel),
+                ("capture_into", after_run.capture_into.is_some()),
+                ("when_newer", !after_run.when_newer.is_empty()),
+            ] {
+                if set {
+                    warning!(
+                        "{option} has no effect on {}",
+                        after_run.labelled("after_run", index),
+                    );
+                }
+            }
+        }
+
+        if self
+            .run
+            .target
+            .targets()
+            .into_iter()
+            .any(|target| matches!(target, Run::Shell(_)))
+            && !matches!(self.run.arg0, Arg0::Program)
+        {
+            warning!("run.arg0 has no effect with run.shell");
+        }
+        if let (Some(nice), Some(clamped)) = (self.run.nice, self.run.nice()) {
+            if i64::from(clamped) != nice {
+                warning!(
+                    "run.nice must be between {NICE_MIN} and {NICE_MAX}, so \
+                     {nice} will be treated as {clamped}"
+                );
+            }
+            // Windows' higher priority classes don't need elevating
+            if cfg!(unix) && clamped < 0 && matches!(is_elevated(), Ok(false)) {
+                warning!(
+                    "run.nice is negative, which needs root (or \
+                     CAP_SYS_NICE), so the program will likely fail to start"
+                );
+            }
+        }
+
+        // A command's own env takes precedence over the top-level one
+        let empty_path = |env: &EnvTable| {
+            env.0
+                .get("PATH")
+                .or(self.env.0.get("PATH"))
+                .is_some_and(|path| path.is_empty())
+        };
+        let envs = self.before_run.iter().map(|step| &step.env);
+        if envs.chain([&self.run.env]).any(empty_path) {
+            warning!(
+                "PATH is set to be empty, so programs won't be found by name"
+            );
+        }
+        if let Some(passthrough) = &self.env_passthrough {
+            if !passthrough.iter().any(|name| name == "PATH")
+                && !self.env.0.contains_key("PATH")
+            {
+                warning!(
+                    "env_passthrough doesn't include PATH, so programs won't \
+                     be found by name unless a command's env sets it"
+                );
+            }
+        }
+        if !self.has_requirements() && self.search_parents {
+            warning!(
+                "search_parents has no effect if there are no required files"
+            );
+        }
+        if !self.has_requirements()
+            && self.root_markers.is_empty()
+            && self.search_children_depth.is_some()
+        {
+            warning!(
+                "search_children_depth has no effect if there are no required \
+                 files"
+            );
+        }
+        if !self.root_markers.is_empty() {
+            for (option, set) in [
+                ("search_parents", self.search_parents),
+                ("search_boundary", self.search_boundary.is_some()),
+                (
+                    "search_children_depth",
+                    self.search_children_depth.is_some(),
+                ),
+                ("cache_root", self.cache_root),
+            ] {
+                if set {
+                    warning!("{option} has no effect if root_markers is set");
+                }
+            }
+        } else if !self.search_parents {
+            if self.max_parent_depth.is_some() {
+                warning!(
+                    "max_parent_depth has no effect if search_parents is false"
+                );
+            }
+            if self.cache_root {
+                warning!("cache_root has no effect if search_parents is false");
+            }
+            if self.search_boundary.is_some() {
+                warning!(
+                    "search_boundary has no effect if search_parents is false"
+                );
+            }
+        }
+        if self.required_files.is_empty()
+            && self.required_files_mode != RequiredFilesMode::All
+        {
+            warning!(
+                "required_files_mode has no effect if there are no required \
+                 files"
+            );
+        }
+        if self.required_files.is_empty() && self.check_readable {
+            warning!(
+                "check_readable has no effect if there are no required files"
+            );
+        }
+        if !self.has_requirements() && self.case_insensitive_files {
+            warning!(
+                "case_insensitive_files has no effect if there are no \
+                 required files"
+            );
+        }
+        if !self.has_requirements()
+            && self.root_markers.is_empty()
+            && self.wait_for_files.is_some()
+        {
+            warning!(
+                "wait_for_files has no effect if there are no required files"
+            );
+        }
+        if self.required_extensions.is_empty()
+            && !self.required_extensions_ignore.is_empty()
+        {
+            warning!(
+                "required_extensions_ignore has no effect if there are no \
+                 required extensions"
+            );
+        }
+        if self.before_run.is_empty()
+            && self.fallback.iter().any(|fallback| fallback.run_before)
+        {
+            warning!(
+                "fallback.run_before has no effect if there are no before_run \
+                 steps"
+            );
+        }
+        if self.fallback.is_empty() && !self.fallback_on_exit_codes.is_empty() {
+            warning!(
+                "fallback_on_exit_codes has no effect if there's no fallback"
+            );
+        }
+        lints
+    }
+}
+
+/// Something wrong with a config, found by [`AppConfig::lints`]
+#[derive(Debug)]
+struct Lint {
+    severity: Severity,
+    message: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    /// The config will still work
+    Warning,
+    /// The config won't work, so isn't run
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// What relative paths to programs and scripts are relative to
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PathsRelativeTo {
+    /// The root found using the required files
+    #[default]
+    Root,
+    /// The directory containing the config file
+    Config,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RequiredFilesMode {
+    /// Every required file must be present
+    #[default]
+    All,
+    /// At least one required file must be present
+    Any,
+}
+
+#[derive(Debug)]
+struct RequiredFile {
+    path: PathBuf,
+    kind: FileKind,
+    content: Option<ContentCheck>,
+    /// Shown if the file is missing, e.g. to say how to create it
+    hint: Option<String>,
+}
+
+impl RequiredFile {
+    /// Checks if this file exists within `dir`. If the path contains any glob
+    /// metacharacters, it's treated as a pattern that must match at least one
+    /// path of the right kind
+    fn exists_in(&self, dir: &Path, checks: FileChecks) -> bool {
+        let case_insensitive = checks.case_insensitive;
+        let Some(pattern) = self
+            .path
+            .to_str()
+            .filter(|name| name.contains(['*', '?', '[']))
+        else {
+            if case_insensitive {
+                return find_case_insensitive(dir, &self.path)
+                    .is_some_and(|path| self.matches(&path, checks));
+            }
+            return self.matches(&dir.join(&self.path), checks);
+        };
+        // The directory itself could contain metacharacters, so must be
+        // escaped before the pattern gets stuck on the end
+        let Some(dir) = dir.to_str() else {
+            warn!(
+                "can't match glob pattern \"{pattern}\" in non-UTF-8 \
+                 directory {}",
+                dir.display(),
+            );
+            return false;
+        };
+        // glob checks components without metacharacters with a plain
+        // exists(), which is case-sensitive, so these are made into patterns
+        let pattern: Cow<str> = if case_insensitive {
+            either_case_pattern(pattern).into()
+        } else {
+            pattern.into()
+        };
+        let full_pattern =
+            Path::new(&glob::Pattern::escape(dir)).join(pattern.as_ref());
+        let full_pattern = full_pattern
+            .to_str()
+            .expect("joined two UTF-8 strings, should still be UTF-8");
+        let options = glob::MatchOptions {
+            case_sensitive: !case_insensitive,
+            ..Default::default()
+        };
+        match glob::glob_with(full_pattern, options) {
+            Ok(mut paths) => paths.any(|path| match path {
+                Ok(path) if self.matches(&path, checks) => {
+                    trace!("\"{pattern}\" matched {}", path.display());
+                    true
+                },
+                Ok(path) => {
+                    trace!(
+                        "\"{pattern}\" matched {}, but it isn't a suitable {}",
+                        path.display(),
+                        self.kind,
+                    );
+                    false
+                },
+                Err(why) => {
+                    debug!("skipping unreadable path while globbing: {why}");
+                    false
+                },
+            }),
+            Err(why) => {
+                warn!("invalid glob pattern \"{pattern}\": {why}");
+                false
+            },
+        }
+    }
+
+    /// Checks if `path` is of the right kind, and has the right content if
+    /// that's required too
+    fn matches(&self, path: &Path, checks: FileChecks) -> bool {
+        if !self.kind.matches(path) {
+            return false;
+        }
+        if checks.readable && !is_readable(path) {
+            warn!(
+                "{} exists, but can't be read, so isn't counted as there",
+                path.display(),
+            );
+            return false;
+        }
+        let Some(check) = &self.content else {
+            return true;
+        };
+        // Only the start of the file is checked, so huge files can't slow
+        // everything down
+        let mut content = Vec::new();
+        let read = fs::File::open(path).and_then(|file| {
+            file.take(MAX_CONTENT_CHECK_BYTES).read_to_end(&mut content)
+        });
+        if let Err(why) = read {
+            debug!(
+                "couldn't read {} to check its content: {why}",
+                path.display()
+            );
+            return false;
+        }
+        let content = String::from_utf8_lossy(&content);
+        let matches = match check {
+            ContentCheck::Contains(needle) => content.contains(needle.as_str()),
+            ContentCheck::Matches(regex) => regex.is_match(&content),
+        };
+        if !matches {
+            trace!("{} doesn't have the required content", path.display());
+        }
+        matches
+    }
+}
+
+/// How much of a required file is read in order to check its content
+const MAX_CONTENT_CHECK_BYTES: u64 = 1024 * 1024;
+
+/// What a required file must contain
+#[derive(Debug)]
+enum ContentCheck {
+    Contains(String),
+    Matches(Regex),
+}
+
+impl<'de> Deserialize<'de> for RequiredFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RequiredFileVisitor;
+
+        impl<'de> Visitor<'de> for RequiredFileVisitor {
+            type Value = RequiredFile;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("path or required file table")
+            }
+
+            fn visit_str<E>(self, path: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(RequiredFile {
+                    path: expand_path(path).map_err(E::custom)?,
+                    kind: FileKind::Any,
+                    content: None,
+                    hint: None,
+                })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut path = None;
+                let mut kind = None;
+                let mut content = None;
+                let mut hint = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let new_content = match key.as_str() {
+                        "hint" => {
+                            hint = Some(map.next_value::<String>()?);
+                            continue;
+                        },
+                        "path" => {
+                            let value = map.next_value::<String>()?;
+                            path = Some(
+                                expand_path(&value)
+                                    .map_err(A::Error::custom)?,
+                            );
+                            continue;
+                        },
+                        "kind" => {
+                            kind = Some(map.next_value::<FileKind>()?);
+                            continue;
+                        },
+                        "contains" => ContentCheck::Contains(map.next_value()?),
+                        "matches" => {
+                            let value = map.next_value::<String>()?;
+                            ContentCheck::Matches(
+                                Regex::new(&value).map_err(A::Error::custom)?,
+                            )
+                        },
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "path", "kind", "contains", "matches", "hint",
+                            ]))
+                        },
+                    };
+                    if content.replace(new_content).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"contains\" or \"matches\" can be \
+                             given",
+                        ));
+                    }
+                }
+                // Only files have content to check
+                let kind = match (kind, &content) {
+                    (Some(FileKind::Dir), Some(_)) => {
+                        return Err(A::Error::custom(
+                            "directories can't have their content checked",
+                        ))
+                    },
+                    (None, Some(_)) => FileKind::File,
+                    (kind, _) => kind.unwrap_or_default(),
+                };
+                Ok(RequiredFile {
+                    path: path
+                        .ok_or_else(|| A::Error::missing_field("path"))?,
+                    kind,
+                    content,
+                    hint,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RequiredFileVisitor)
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FileKind {
+    /// Either a file or a directory
+    #[default]
+    Any,
+    File,
+    Dir,
+}
+
+/// How strictly required files are checked for
+#[derive(Debug, Default, Copy, Clone)]
+struct FileChecks {
+    /// Names can differ in case from what's on disk, even if the filesystem
+    /// is case-sensitive
+    case_insensitive: bool,
+    /// Files and directories must be readable by gig too, not just exist
+    readable: bool,
+}
+
+/// Whether `path` can be read, by opening it (or listing it, for a directory).
+/// This is more reliable than checking permissions, as it also accounts for
+/// ACLs, and how permissions work differently on each platform
+fn is_readable(path: &Path) -> bool {
+    if path.is_dir() {
+        fs::read_dir(path).is_ok()
+    } else {
+        fs::File::open(path).is_ok()
+    }
+}
+
+impl FileKind {
+    fn matches(self, path: &Path) -> bool {
+        match self {
+            FileKind::Any => path.exists(),
+            FileKind::File => path.is_file(),
+            FileKind::Dir => path.is_dir(),
+        }
+    }
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FileKind::Any => "file or directory",
+            FileKind::File => "file",
+            FileKind::Dir => "directory",
+        })
+    }
+}
+
+#[derive(Debug)]
+struct BeforeRun {
+    name: Option<String>,
+    task: Task,
+    env: EnvTable,
+    /// Start from an empty environment, instead of inheriting gig's
+    clear_env: bool,
+    allow_failure: bool,
+    /// Run at the same time as the steps either side of it that also have
+    /// `parallel` set
+    parallel: bool,
+    timeout: Option<Duration>,
+    working_directory: Option<PathBuf>,
+    stdout: OutputMode,
+    stderr: OutputMode,
+    /// What to run `script_path` with, instead of picking automatically
+    interpreter: Option<String>,
+    /// Inputs that, if changed, mean the step needs to run again
+    when_newer: Vec<PathBuf>,
+    /// The outputs of the step
+    than: Vec<PathBuf>,
+    /// How `command` is split into arguments
+    shell: CommandSyntax,
+    /// The variable to give `run` this step's (trimmed) stdout in
+    capture_into: Option<String>,
+}
+
+impl BeforeRun {
+    /// Whether the step can be skipped, as every output in `than` is newer
+    /// than every input in `when_newer`
+    fn is_up_to_date(&self, root: &Path) -> bool {
+        if self.than.is_empty() {
+            return false;
+        }
+        let mtime = |path: &PathBuf| {
+            root.join(path)
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        // Anything missing means it's definitely not up to date
+        let Some(newest_input) = self
+            .when_newer
+            .iter()
+            .map(mtime)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return false;
+        };
+        let Some(oldest_output) =
+            self.than.iter().map(mtime).collect::<Option<Vec<_>>>()
+        else {
+            return false;
+        };
+        match (newest_input.iter().max(), oldest_output.iter().min()) {
+            (Some(input), Some(output)) => output > input,
+            _ => false,
+        }
+    }
+
+    /// Describes the step for use in messages, given its index in the list of
+    /// steps
+    fn label(&self, index: usize) -> String {
+        self.labelled("before_run", index)
+    }
+
+    /// How to refer to the step in messages, as part of `section` (e.g.
+    /// `after_run`)
+    fn labelled(&self, section: &str, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("{section} step \"{name}\""),
+            None => format!("{section} step #{}", index + 1),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BeforeRun {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BeforeRunVisitor;
+
+        impl<'de> Visitor<'de> for BeforeRunVisitor {
+            type Value = BeforeRun;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("before_run table")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut name = None;
+                let mut task = None;
+                let mut env = EnvTable::default();
+                let mut allow_failure = false;
+                let mut clear_env = false;
+                let mut parallel = false;
+                let mut timeout = None;
+                let mut working_directory = None;
+                let mut stdout = OutputMode::default();
+                let mut stderr = OutputMode::default();
+                let mut interpreter = None;
+                let mut when_newer = Vec::new();
+                let mut than = Vec::new();
+                let mut shell = CommandSyntax::default();
+                let mut capture_into = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let new_task = match key.as_str() {
+                        "capture_into" => {
+                            let value = map.next_value::<String>()?;
+                            if value.is_empty() || value.contains('=') {
+                                return Err(A::Error::custom(format!(
+                                    "capture_into should be a variable name, \
+                                     but is \"{value}\""
+                                )));
+                            }
+                            capture_into = Some(value);
+                            continue;
+                        },
+                        "shell" => {
+                            shell = map.next_value::<CommandSyntax>()?;
+                            continue;
+                        },
+                        "when_newer" => {
+                            when_newer = map
+                                .next_value::<Vec<String>>()?
+                                .iter()
+                                .map(|path| expand_path(path))
+                                .collect::<Result<_, _>>()
+                                .map_err(A::Error::custom)?;
+                            continue;
+                        },
+                        "than" => {
+                            than = map
+                                .next_value::<Vec<String>>()?
+                                .iter()
+                                .map(|path| expand_path(path))
+                                .collect::<Result<_, _>>()
+                                .map_err(A::Error::custom)?;
+                            continue;
+                        },
+                        "interpreter" => {
+                            interpreter = Some(map.next_value::<String>()?);
+                            continue;
+                        },
+                        "stdout" => {
+                            stdout = map.next_value::<OutputMode>()?;
+                            continue;
+                        },
+                        "stderr" => {
+                            stderr = map.next_value::<OutputMode>()?;
+                            continue;
+                        },
+                        "name" => {
+                            name = Some(map.next_value::<String>()?);
+                            continue;
+                        },
+                        "env" => {
+                            env = map.next_value::<EnvTable>()?;
+                            continue;
+                        },
+                        "allow_failure" => {
+                            allow_failure = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "clear_env" => {
+                            clear_env = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "parallel" => {
+                            parallel = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "working_directory" => {
+                            let value = map.next_value::<String>()?;
+                            working_directory = Some(
+                                expand_path(&value)
+                                    .map_err(A::Error::custom)?,
+                            );
+                            continue;
+                        },
+                        "timeout" => {
+                            timeout = Some(parse_duration(map.next_value()?)?);
+                            continue;
+                        },
+                        "command" => {
+                            let value = map.next_value::<String>()?;
+                            if !value.is_empty() {
+                                Task::Command(value)
+                            } else {
+                                return Err(A::Error::custom(
+                                    "command can't be empty",
+                                ));
+                            }
+                        },
+                        "script_path" => {
+                            let value = map.next_value::<String>()?;
+                            // Checked once the root is known, as it's relative
+                            // to the root or config file
+                            let path = expand_path(&value)
+                                .map_err(A::Error::custom)?;
+                            Task::ScriptPath(path)
+                        },
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "command",
+                                "script_path",
+                                "name",
+                                "env",
+                                "clear_env",
+                                "allow_failure",
+                                "parallel",
+                                "timeout",
+                                "working_directory",
+                                "stdout",
+                                "stderr",
+                                "interpreter",
+                                "when_newer",
+                                "than",
+                                "shell",
+                                "capture_into",
+                            ]))
+                        },
+                    };
+                    if task.replace(new_task).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"command\" or \"script_path\" can \
+                             be given",
+                        ));
+                    }
+                }
+                let task = task.ok_or_else(|| {
+                    A::Error::custom(
+                        "before_run needs either \"command\" or \
+                         \"script_path\"",
+                    )
+                })?;
+                if when_newer.is_empty() != than.is_empty() {
+                    return Err(A::Error::custom(
+                        "\"when_newer\" and \"than\" must be given together",
+                    ));
+                }
+                Ok(BeforeRun {
+                    name,
+                    task,
+                    env,
+                    clear_env,
+                    allow_failure,
+                    parallel,
+                    timeout,
+                    working_directory,
+                    stdout,
+                    stderr,
+                    interpreter,
+                    when_newer,
+                    than,
+                    shell,
+                    capture_into,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(BeforeRunVisitor)
+    }
+}
+
+/// Environment variables to set for a command. Any variables referenced in
+/// the values are expanded at load time, whereas placeholders (see
+/// [`ENV_PLACEHOLDERS`]) are filled in when the command is made. Literal braces
+/// in the values are escaped as `{{` and `}}`, so only placeholders are left
+#[derive(Debug, Default)]
+struct EnvTable(BTreeMap<String, String>);
+
+impl EnvTable {
+    /// Sets the variables for `command`, filling in any placeholders
+    fn apply(
+        &self,
+        command: &mut Command,
+        root: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        for (name, value) in &self.0 {
+            command.env(name, fill_placeholders(value, root)?);
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for EnvTable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut table = BTreeMap::<String, String>::deserialize(deserializer)?;
+        for value in table.values_mut() {
+            *value = env_template(value).map_err(D::Error::custom)?;
+        }
+        Ok(EnvTable(table))
+    }
+}
+
+/// The placeholders that can be used in `env` values, e.g. `{root}/config`
+const ENV_PLACEHOLDERS: &[&str] = &["root", "name", "cwd"];
+
+/// If `value` starts with something that looks like a placeholder, returns its
+/// name and length
+fn placeholder_at(value: &str) -> Option<(&str, usize)> {
+    let end = value.strip_prefix('{')?.find('}')? + 1;
+    let name = &value[1..end];
+    let is_name = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+    is_name.then_some((name, end + 1))
+}
+
+/// Expands the environment variables in `value`, leaving any placeholders to
+/// be filled in later by [`fill_placeholders`]. `{{` and `}}` can be used for
+/// literal braces
+fn env_template(value: &str) -> anyhow::Result<String> {
+    env_template_with(value, |name| env::var(name))
+}
+
+/// [`env_template`], getting variables from `var` instead of the environment
+fn env_template_with(
+    value: &str,
+    var: impl Fn(&str) -> Result<String, env::VarError>,
+) -> anyhow::Result<String> {
+    let mut template = String::new();
+    let mut literal = String::new();
+    let flush = |literal: &mut String, template: &mut String| {
+        let expanded = expand_vars_with(literal, &var)?;
+        template.push_str(&expanded.replace('{', "{{").replace('}', "}}"));
+        literal.clear();
+        anyhow::Ok(())
+    };
+    let mut rest = value;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("$$") {
+            literal.push_str("$$");
+            2
+        } else if rest.starts_with("${") {
+            // Variables are expanded by expand_vars_with, not mistaken for
+            // placeholders
+            let len = rest.find('}').map_or(rest.len(), |end| end + 1);
+            literal.push_str(&rest[..len]);
+            len
+        } else if rest.starts_with("{{") || rest.starts_with("}}") {
+            literal.push(c);
+            2
+        } else if let Some((name, len)) = placeholder_at(rest) {
+            if !ENV_PLACEHOLDERS.contains(&name) {
+                bail!(
+                    "unknown placeholder {{{name}}} in \"{value}\", expected \
+                     one of {{root}}, {{name}}, or {{cwd}} (use {{{{ and }}}} \
+                     for literal braces)"
+                );
+            }
+            flush(&mut literal, &mut template)?;
+            template.push_str(&rest[..len]);
+            len
+        } else {
+            literal.push(c);
+            c.len_utf8()
+        };
+        rest = &rest[len..];
+    }
+    flush(&mut literal, &mut template)?;
+    Ok(template)
+}
+
+/// Fills in the placeholders in an `env` value made by [`env_template`]. The
+/// root isn't known for fallbacks, so `{root}` can't be used for them
+fn fill_placeholders(
+    template: &str,
+    root: Option<&Path>,
+) -> anyhow::Result<String> {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("{{") || rest.starts_with("}}") {
+            filled.push(c);
+            2
+        } else if let Some((name, len)) = placeholder_at(rest) {
+            let value = match name {
+                "root" => root.ok_or_else(|| {
+                    anyhow!(
+                        "{{root}} can't be used in \"{template}\", as there's \
+                         no root for fallbacks"
+                    )
+                })?,
+                "cwd" => CWD.as_path(),
+                "name" => Path::new(NAME.as_ref()),
+                unknown => bail!("unknown placeholder {{{unknown}}}"),
+            };
+            filled.push_str(&value.to_string_lossy());
+            len
+        } else {
+            filled.push(c);
+            c.len_utf8()
+        };
+        rest = &rest[len..];
+    }
+    Ok(filled)
+}
+
+/// The quoting rules used to split a command string into arguments
+#[derive(Debug, Default, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CommandSyntax {
+    /// Like a POSIX shell, e.g. `sh` or `bash`
+    #[default]
+    Posix,
+    /// Like Windows programs parse their command line
+    Cmd,
+    /// Like PowerShell
+    Powershell,
+    /// Not split at all, the whole string is the program
+    None,
+}
+
+/// Makes a command from `cmd_str`, split into the program and its arguments
+/// according to `syntax`
+fn command_from_str(cmd_str: &str, syntax: CommandSyntax) -> Command {
+    let mut args = split_command(cmd_str, syntax).into_iter();
+    let mut command = Command::new(args.next().unwrap_or_default());
+    command.args(args);
+    command
+}
+
+/// Splits `cmd_str` into arguments using the quoting rules of `syntax`
+fn split_command(cmd_str: &str, syntax: CommandSyntax) -> Vec<String> {
+    match syntax {
+        CommandSyntax::Posix => Shlex::new(cmd_str).collect(),
+        CommandSyntax::Cmd => split_windows_command(cmd_str),
+        CommandSyntax::Powershell => split_powershell_command(cmd_str),
+        CommandSyntax::None => vec![cmd_str.to_owned()],
+    }
+}
+
+/// Splits arguments the way the Microsoft C runtime does. Double quotes group
+/// arguments, and backslashes are only special before a double quote
+fn split_windows_command(cmd_str: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = cmd_str.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+                if chars.peek() == Some(&'"') {
+                    // 2n backslashes are n backslashes then a quote that
+                    // starts/ends quoting, 2n + 1 are n then a literal quote
+                    arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        arg.push('"');
+                        chars.next();
+                    }
+                } else {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                }
+                in_arg = true;
+            },
+            // A doubled quote within quotes is a literal quote
+            '"' if quoted && chars.next_if_eq(&'"').is_some() => arg.push('"'),
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            },
+            c => {
+                arg.push(c);
+                in_arg = true;
+            },
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    args
+}
+
+/// Splits arguments the way PowerShell does. Single quotes are literal (with
+/// `''` for a quote), and backticks escape the next character elsewhere
+fn split_powershell_command(cmd_str: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = cmd_str.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', Some('\'')) if chars.next_if_eq(&'\'').is_some() => {
+                arg.push('\'');
+            },
+            ('"', Some('"')) if chars.next_if_eq(&'"').is_some() => {
+                arg.push('"');
+            },
+            (c, Some(open)) if c == open => quote = None,
+            ('`', Some('"') | None) => {
+                if let Some(escaped) = chars.next() {
+                    arg.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        '0' => '\0',
+                        escaped => escaped,
+                    });
+                }
+                in_arg = true;
+            },
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                in_arg = true;
+            },
+            (c, None) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            },
+            (c, _) => {
+                arg.push(c);
+                in_arg = true;
+            },
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    args
+}
+
+#[derive(Debug)]
+enum Task {
+    Command(String),
+    ScriptPath(PathBuf),
+}
+
+#[derive(Debug)]
+struct RunConfig {
+    target: Run,
+    timeout: Option<Duration>,
+    env: EnvTable,
+    env_remove: Vec<String>,
+    /// Start from an empty environment, instead of inheriting gig's
+    clear_env: bool,
+    args_prepend: Vec<String>,
+    args_append: Vec<String>,
+    /// Where gig's arguments go, replacing any `{}` or `{args}` entries
+    args_template: Option<Vec<String>>,
+    /// Which of gig's arguments aren't passed on
+    args_filter: ArgsFilter,
+    working_directory: Option<PathBuf>,
+    retries: u32,
+    retry_delay: Duration,
+    /// Only these exit codes are retried, or any failure if empty
+    retry_on_exit_codes: Vec<i32>,
+    /// Niceness to run the program with, as given (see [`RunConfig::nice`])
+    nice: Option<i64>,
+    /// The umask to run the program with, only used on Unix
+    umask: Option<u16>,
+    arg0: Arg0,
+    stdin: InputMode,
+    /// Replaces the target with a path to the version pinned for a tool
+    tool_versions: Option<ToolVersions>,
+}
+
+/// The longest the delay between retries grows to
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+impl RunConfig {
+    /// The niceness to run the program with, clamped to what Unix allows
+    fn nice(&self) -> Option<i32> {
+        self.nice.map(|nice| nice.clamp(NICE_MIN, NICE_MAX) as i32)
+    }
+
+    /// How long to wait before the given retry (counting from 1). The delay
+    /// doubles each time, up to [`MAX_RETRY_DELAY`] (or retry_delay itself, if
+    /// that's longer)
+    fn retry_delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.retry_delay
+            .saturating_mul(factor)
+            .min(self.retry_delay.max(MAX_RETRY_DELAY))
+    }
+
+    /// Whether the program exiting with `status` should be retried, if there
+    /// are retries left
+    fn should_retry(&self, status: ExitStatus) -> bool {
+        if status.success() {
+            return false;
+        }
+        self.retry_on_exit_codes.is_empty()
+            || status
+                .code()
+                .is_some_and(|code| self.retry_on_exit_codes.contains(&code))
+    }
+}
+
+impl<'de> Deserialize<'de> for RunConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RunConfigVisitor;
+
+        impl<'de> Visitor<'de> for RunConfigVisitor {
+            type Value = RunConfig;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("run table")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut target = None;
+                let mut timeout = None;
+                let mut env = EnvTable::default();
+                let mut env_remove = Vec::new();
+                let mut clear_env = false;
+                let mut args_prepend = Vec::new();
+                let mut args_append = Vec::new();
+                let mut args_template = None;
+                let mut args_filter = ArgsFilter::default();
+                let mut working_directory = None;
+                let mut retries = 0;
+                let mut retry_delay = Duration::ZERO;
+                let mut retry_on_exit_codes = Vec::new();
+                let mut nice = None;
+                let mut umask = None;
+                let mut arg0 = Arg0::default();
+                let mut stdin = InputMode::default();
+                let mut tool_versions = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let new_target = match key.as_str() {
+                        "env" => {
+                            env = map.next_value::<EnvTable>()?;
+                            continue;
+                        },
+                        "env_remove" => {
+                            env_remove = map.next_value::<Vec<String>>()?;
+                            continue;
+                        },
+                        "clear_env" => {
+                            clear_env = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "args_prepend" => {
+                            args_prepend = map.next_value::<Vec<String>>()?;
+                            continue;
+                        },
+                        "args_append" => {
+                            args_append = map.next_value::<Vec<String>>()?;
+                            continue;
+                        },
+                        "args_filter" => {
+                            args_filter = map.next_value::<ArgsFilter>()?;
+                            continue;
+                        },
+                        "retries" => {
+                            retries = map.next_value::<u32>()?;
+                            continue;
+                        },
+                        "retry_delay" => {
+                            retry_delay = parse_duration(map.next_value()?)?;
+                            continue;
+                        },
+                        "retry_on_exit_codes" => {
+                            retry_on_exit_codes =
+                                map.next_value::<Vec<i32>>()?;
+                            continue;
+                        },
+                        "nice" => {
+                            nice = Some(map.next_value::<i64>()?);
+                            continue;
+                        },
+                        "arg0" => {
+                            arg0 = map.next_value::<Arg0>()?;
+                            continue;
+                        },
+                        "stdin" => {
+                            stdin = map.next_value::<InputMode>()?;
+                            continue;
+                        },
+                        "tool_versions" => {
+                            tool_versions = Some(map.next_value()?);
+                            continue;
+                        },
+                        "umask" => {
+                            let value = map.next_value::<String>()?;
+                            umask = Some(
+                                parse_umask(&value)
+                                    .map_err(A::Error::custom)?,
+                            );
+                            continue;
+                        },
+                        "args_template" => {
+                            let template = map.next_value::<Vec<String>>()?;
+                            if !template
+                                .iter()
+                                .any(|arg| is_args_placeholder(arg))
+                            {
+                                return Err(A::Error::custom(
+                                    "args_template needs a \"{}\" or \
+                                     \"{args}\" entry to put arguments in",
+                                ));
+                            }
+                            args_template = Some(template);
+                            continue;
+                        },
+                        "timeout" => {
+                            timeout = Some(parse_duration(map.next_value()?)?);
+                            continue;
+                        },
+                        "working_directory" => {
+                            let value = map.next_value::<String>()?;
+                            working_directory = Some(
+                                expand_path(&value)
+                                    .map_err(A::Error::custom)?,
+                            );
+                            continue;
+                        },
+                        "subcommand_of" => Run::SubcommandOf(map.next_value()?),
+                        "via" => Run::Via(map.next_value()?),
+                        "shell" => Run::Shell(map.next_value()?),
+                        "path" => map.next_value::<RunPath>()?.0,
+                        "dispatch" => Run::Dispatch(map.next_value()?),
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "subcommand_of",
+                                "path",
+                                "via",
+                                "shell",
+                                "dispatch",
+                                "timeout",
+                                "env",
+                                "env_remove",
+                                "clear_env",
+                                "args_prepend",
+                                "args_append",
+                                "args_template",
+                                "args_filter",
+                                "working_directory",
+                                "retries",
+                                "retry_delay",
+                                "retry_on_exit_codes",
+                                "nice",
+                                "umask",
+                                "arg0",
+                                "stdin",
+                                "tool_versions",
+                            ]))
+                        },
+                    };
+                    if target.replace(new_target).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"subcommand_of\", \"path\", \
+                             \"via\", \"shell\", or \"dispatch\" can be given",
+                        ));
+                    }
+                }
+                let target = target.ok_or_else(|| {
+                    A::Error::custom(
+                        "run needs one of \"subcommand_of\", \"path\", \
+                         \"via\", \"shell\", or \"dispatch\"",
+                    )
+                })?;
+                Ok(RunConfig {
+                    target,
+                    timeout,
+                    env,
+                    env_remove,
+                    clear_env,
+                    args_prepend,
+                    args_append,
+                    args_template,
+                    args_filter,
+                    working_directory,
+                    retries,
+                    retry_delay,
+                    retry_on_exit_codes,
+                    nice,
+                    umask,
+                    arg0,
+                    stdin,
+                    tool_versions,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(RunConfigVisitor)
+    }
+}
+
+/// What the program run is told its name (argv\[0\]) is, for multi-call
+/// programs that change what they do based on it. Only used on Unix
+#[derive(Debug, Default)]
+enum Arg0 {
+    /// The program's path, as usual
+    #[default]
+    Program,
+    /// The name gig is running as, from `arg0 = true`
+    Name,
+    Custom(String),
+}
+
+impl<'de> Deserialize<'de> for Arg0 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Arg0Visitor;
+
+        impl Visitor<'_> for Arg0Visitor {
+            type Value = Arg0;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("boolean or name")
+            }
+
+            fn visit_bool<E>(self, as_name: bool) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(if as_name { Arg0::Name } else { Arg0::Program })
+            }
+
+            fn visit_str<E>(self, arg0: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                if arg0.is_empty() {
+                    return Err(E::custom("arg0 can't be empty"));
+                }
+                Ok(Arg0::Custom(arg0.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(Arg0Visitor)
+    }
+}
+
+/// Arguments given to gig that shouldn't be passed on to `run`, either exactly
+/// matching one of `drop`, or matching the `matches` regex
+#[derive(Debug, Default)]
+struct ArgsFilter {
+    drop: Vec<String>,
+    matches: Option<Regex>,
+}
+
+impl ArgsFilter {
+    /// Whether `arg` should be passed on. Non-UTF-8 arguments can't match the
+    /// regex, so are only dropped if they're in `drop`
+    fn keeps(&self, arg: &OsStr) -> bool {
+        if self.drop.iter().any(|drop| arg == OsStr::new(drop)) {
+            return false;
+        }
+        !self
+            .matches
+            .as_ref()
+            .zip(arg.to_str())
+            .is_some_and(|(regex, arg)| regex.is_match(arg))
+    }
+}
+
+impl<'de> Deserialize<'de> for ArgsFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ArgsFilterVisitor;
+
+        impl<'de> Visitor<'de> for ArgsFilterVisitor {
+            type Value = ArgsFilter;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("list of arguments or args_filter table")
+            }
+
+            fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                Ok(ArgsFilter {
+                    drop: Vec::deserialize(SeqAccessDeserializer::new(seq))?,
+                    matches: None,
+                })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut filter = ArgsFilter::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "drop" => filter.drop = map.next_value()?,
+                        "matches" => {
+                            let value = map.next_value::<String>()?;
+                            filter.matches = Some(
+                                Regex::new(&value).map_err(A::Error::custom)?,
+                            );
+                        },
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "drop", "matches",
+                            ]))
+                        },
+                    }
+                }
+                Ok(filter)
+            }
+        }
+
+        deserializer.deserialize_any(ArgsFilterVisitor)
+    }
+}
+
+/// `run.path`, which is a folder if it's `{ folder = "..." }` or ends with a
+/// separator, otherwise an executable
+struct RunPath(Run);
+
+impl<'de> Deserialize<'de> for RunPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RunPathVisitor;
+
+        impl<'de> Visitor<'de> for RunPathVisitor {
+            type Value = RunPath;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("path, or table with folder or exe")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                let path = expand_path(value).map_err(E::custom)?;
+                let is_folder = value.ends_with('/')
+                    || (cfg!(windows) && value.ends_with('\\'));
+                Ok(RunPath(if is_folder {
+                    Run::PrependFolder(path)
+                } else {
+                    Run::Executable(path)
+                }))
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut run = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let value = match key.as_str() {
+                        "folder" | "exe" => map.next_value::<String>()?,
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "folder", "exe",
+                            ]))
+                        },
+                    };
+                    let path = expand_path(&value).map_err(A::Error::custom)?;
+                    let new_run = if key == "folder" {
+                        Run::PrependFolder(path)
+                    } else {
+                        Run::Executable(path)
+                    };
+                    if run.replace(new_run).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"folder\" or \"exe\" can be given",
+                        ));
+                    }
+                }
+                run.map(RunPath).ok_or_else(|| {
+                    A::Error::custom("run.path needs \"folder\" or \"exe\"")
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RunPathVisitor)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Run {
+    SubcommandOf(SubcommandOf),
+    PrependFolder(PathBuf),
+    Executable(PathBuf),
+    Via(Via),
+    /// A command line run by the platform's shell
+    Shell(String),
+    Dispatch(Dispatch),
+}
+
+impl Run {
+    /// The target to run for `args`, and the arguments to pass on to it. This
+    /// is only different for [`Run::Dispatch`], where the first argument picks
+    /// the target and isn't passed on
+    fn dispatch<'a>(
+        &'a self,
+        args: &'a [OsString],
+    ) -> anyhow::Result<(&'a Run, &'a [OsString])> {
+        let Run::Dispatch(dispatch) = self else {
+            return Ok((self, args));
+        };
+        let first = args.first().and_then(|arg| arg.to_str());
+        match first.and_then(|first| dispatch.routes.get(first)) {
+            Some(target) => {
+                debug!("dispatching {} to its run target", first.unwrap());
+                Ok((target, &args[1..]))
+            },
+            None => match &dispatch.default {
+                Some(default) => Ok((default, args)),
+                None => bail!(
+                    "run.dispatch has no entry for {}, and there's no default \
+                     (expected one of {})",
+                    first.map_or("no arguments".to_owned(), |first| {
+                        format!("{first:?}")
+                    }),
+                    dispatch
+                        .routes
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            },
+        }
+    }
+
+    /// Every target that could be run, which is more than one with
+    /// [`Run::Dispatch`]
+    fn targets(&self) -> Vec<&Run> {
+        match self {
+            Run::Dispatch(dispatch) => dispatch
+                .routes
+                .values()
+                .chain(dispatch.default.as_deref())
+                .collect(),
+            run => vec![run],
+        }
+    }
+}
+
+/// Runs one of several targets, picked by the first argument gig is given,
+/// like git's subcommands are separate programs
+#[derive(Debug, Clone)]
+struct Dispatch {
+    routes: BTreeMap<String, Run>,
+    /// Run for any other first argument (or none), which is passed on to it
+    default: Option<Box<Run>>,
+}
+
+impl<'de> Deserialize<'de> for Dispatch {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DispatchVisitor;
+
+        impl<'de> Visitor<'de> for DispatchVisitor {
+            type Value = Dispatch;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("table of subcommands to run targets")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut routes = BTreeMap::new();
+                let mut default = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let target = map.next_value::<RunTarget>()?.0;
+                    if key == "default" {
+                        default = Some(Box::new(target));
+                    } else {
+                        routes.insert(key, target);
+                    }
+                }
+                if routes.is_empty() {
+                    return Err(A::Error::custom(
+                        "run.dispatch needs at least one subcommand",
+                    ));
+                }
+                Ok(Dispatch { routes, default })
+            }
+        }
+
+        deserializer.deserialize_map(DispatchVisitor)
+    }
+}
+
+/// A target in `run.dispatch`, which is a path like `run.path`, or a table
+/// with one of the keys `run` can pick its target with
+struct RunTarget(Run);
+
+impl<'de> Deserialize<'de> for RunTarget {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RunTargetVisitor;
+
+        impl<'de> Visitor<'de> for RunTargetVisitor {
+            type Value = RunTarget;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("path, or run target table")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                let path = RunPath::deserialize(StrDeserializer::new(value))?;
+                Ok(RunTarget(path.0))
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut target = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    let new_target = match key.as_str() {
+                        "subcommand_of" => Run::SubcommandOf(map.next_value()?),
+                        "via" => Run::Via(map.next_value()?),
+                        "shell" => Run::Shell(map.next_value()?),
+                        "path" => map.next_value::<RunPath>()?.0,
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "subcommand_of",
+                                "path",
+                                "via",
+                                "shell",
+                            ]))
+                        },
+                    };
+                    if target.replace(new_target).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"subcommand_of\", \"path\", \
+                             \"via\", or \"shell\" can be given",
+                        ));
+                    }
+                }
+                target.map(RunTarget).ok_or_else(|| {
+                    A::Error::custom(
+                        "dispatch targets need one of \"subcommand_of\", \
+                         \"path\", \"via\", or \"shell\"",
+                    )
+                })
+            }
+        }
+
+        deserializer.deserialize_any(RunTargetVisitor)
+    }
+}
+
+/// Makes a command that runs `line` with the platform's shell, with `args`
+/// appended. On Unix, the arguments are passed as the shell's positional
+/// parameters, so they can't be interpreted by the shell. On Windows, `cmd`
+/// has no equivalent, so they're quoted and escaped instead
+fn shell_command(line: &str, args: &[&OsStr]) -> Command {
+    #[cfg(unix)]
+    {
+        let mut command = Command::new("sh");
+        // The first argument after the script becomes $0
+        command
+            .arg("-c")
+            .arg(format!("{line} \"$@\""))
+            .arg(NAME.as_ref());
+        command.args(args);
+        command
+    }
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("cmd");
+        // cmd parses its command line itself, so the arguments are given
+        // as-is instead of letting Command quote them
+        command.raw_arg("/C").raw_arg(line);
+        for arg in args {
+            command.raw_arg(cmd_escape(&arg.to_string_lossy()));
+        }
+        command
+    }
+}
+
+/// Quotes `arg` for the program's own argument parsing, then escapes every
+/// character cmd treats specially (including the quotes) with `^`, so cmd
+/// passes it through unchanged. `%` is the exception, which can still expand
+/// variables
+#[cfg(windows)]
+fn cmd_escape(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            // Backslashes are only special before a quote, where they and the
+            // quote itself need escaping
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes + 1));
+                backslashes = 0;
+            },
+            _ => backslashes = 0,
+        }
+        quoted.push(c);
+    }
+    // Likewise for the closing quote
+    quoted.extend(std::iter::repeat_n('\\', backslashes));
+    quoted.push('"');
+    let mut escaped = String::with_capacity(quoted.len());
+    for c in quoted.chars() {
+        if "()%!^\"<>&|".contains(c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A command that outputs the path of the program to run
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Via {
+    resolver: String,
+    /// The path output by the resolver, so it's only run once
+    #[serde(skip)]
+    resolved: OnceCell<PathBuf>,
+}
+
+/// A program to run a subcommand of. The subcommand is [`NAME`] unless given
+#[derive(Debug, Clone)]
+struct SubcommandOf {
+    program: String,
+    subcommand: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for SubcommandOf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SubcommandOfVisitor;
+
+        impl<'de> Visitor<'de> for SubcommandOfVisitor {
+            type Value = SubcommandOf;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("program name or subcommand_of table")
+            }
+
+            fn visit_str<E>(self, program: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(SubcommandOf {
+                    program: program.to_owned(),
+                    subcommand: None,
+                })
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut program = None;
+                let mut subcommand = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "program" => program = Some(map.next_value()?),
+                        "subcommand" => subcommand = Some(map.next_value()?),
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "program",
+                                "subcommand",
+                            ]))
+                        },
+                    }
+                }
+                Ok(SubcommandOf {
+                    program: program
+                        .ok_or_else(|| A::Error::missing_field("program"))?,
+                    subcommand,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(SubcommandOfVisitor)
+    }
+}
+
+/// A tool's version pinned in an asdf-style `.tool-versions` file, and where
+/// that version of it is installed
+#[derive(Debug)]
+struct ToolVersions {
+    tool: String,
+    /// Where the program is, with `{version}` in place of the version
+    path: String,
+}
+
+impl ToolVersions {
+    /// The path to the pinned version of the tool, if `root` has a
+    /// `.tool-versions` file pinning one
+    fn path_in(&self, root: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let file = root.join(TOOL_VERSIONS_FILE);
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                debug!("no {TOOL_VERSIONS_FILE} in {}", root.display());
+                return Ok(None);
+            },
+            Err(why) => {
+                return Err(why).with_context(|| {
+                    format!("failed to read {}", file.display())
+                });
+            },
+        };
+        let Some(version) = pinned_version(&contents, &self.tool) else {
+            debug!(
+                "{TOOL_VERSIONS_FILE} doesn't pin a version of {}",
+                self.tool
+            );
+            return Ok(None);
+        };
+        debug!("{TOOL_VERSIONS_FILE} pins {} {version}", self.tool);
+        let path = expand_path(&self.path.replace("{version}", version))
+            .context("invalid tool_versions path")?;
+        Ok(Some(path))
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolVersions {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ToolVersionsVisitor;
+
+        impl<'de> Visitor<'de> for ToolVersionsVisitor {
+            type Value = ToolVersions;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("tool_versions table")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut tool = None;
+                let mut path = None::<String>;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "tool" => tool = Some(map.next_value()?),
+                        "path" => path = Some(map.next_value()?),
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "tool", "path",
+                            ]))
+                        },
+                    }
+                }
+                let path =
+                    path.ok_or_else(|| A::Error::missing_field("path"))?;
+                if !path.contains("{version}") {
+                    return Err(A::Error::custom(
+                        "tool_versions path needs a \"{version}\" in it",
+                    ));
+                }
+                Ok(ToolVersions {
+                    tool: tool
+                        .ok_or_else(|| A::Error::missing_field("tool"))?,
+                    path,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(ToolVersionsVisitor)
+    }
+}
+
+/// The first version of `tool` given in the contents of a `.tool-versions`
+/// file. `system` isn't counted as a version, as that means using whatever
+/// would've been used without it
+fn pinned_version<'a>(contents: &'a str, tool: &str) -> Option<&'a str> {
+    contents.lines().find_map(|line| {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        let mut words = line.split_whitespace();
+        if words.next() != Some(tool) {
+            return None;
+        }
+        words.next().filter(|&version| version != "system")
+    })
+}
+
+/// A `before_run` step exiting unsuccessfully, which stops anything else
+/// being run
+#[derive(Debug)]
+struct BeforeRunFailed {
+    message: String,
+    status: ExitStatus,
+}
+
+impl fmt::Display for BeforeRunFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for BeforeRunFailed {}
+
+/// Why the root couldn't be found
+#[derive(Debug)]
+pub struct RootNotFound {
+    /// The required files missing from the directory the search started in
+    pub missing: Vec<PathBuf>,
+    /// The hints given for any of the missing files, to explain how to get
+    /// them
+    pub hints: Vec<(PathBuf, String)>,
+    /// The directory the search started in
+    pub cwd: PathBuf,
+    /// The last directory searched, which is `cwd` unless parents were
+    /// searched too
+    pub searched_up_to: PathBuf,
+}
+
+impl fmt::Display for RootNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = self
+            .missing
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "missing {missing} in {}", self.cwd.display())?;
+        if self.searched_up_to != self.cwd {
+            write!(
+                f,
+                " (searched parents up to {})",
+                self.searched_up_to.display(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RootNotFound {}
+
+/// Displays a string as a JSON string literal, with quotes and escaping
+pub struct JsonString<'a>(pub &'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// Clears the environment `command` would inherit from gig. A minimal `PATH`
+/// is kept so programs can still be found, along with `SystemRoot` on
+/// Windows, which many programs can't start without
+fn clear_env(command: &mut Command) {
+    command.env_clear();
+    #[cfg(windows)]
+    let path = match env::var_os("SystemRoot") {
+        Some(system_root) => {
+            let system_root = PathBuf::from(system_root);
+            command.env("SystemRoot", &system_root);
+            env::join_paths([system_root.join("System32"), system_root])
+                .expect("SystemRoot is already a valid path")
+        },
+        None => OsString::new(),
+    };
+    #[cfg(unix)]
+    let path = OsString::from("/usr/local/bin:/usr/bin:/bin");
+    trace!(
+        "cleared environment, PATH is now {}",
+        path.to_string_lossy()
+    );
+    command.env("PATH", path);
+}
+
+/// Whether `arg` in an `args_template` should be replaced with gig's arguments
+fn is_args_placeholder(arg: &str) -> bool {
+    arg == "{}" || arg == "{args}"
+}
+
+/// The most config files [`suggest_config_files`] will suggest
+const MAX_CONFIG_SUGGESTIONS: usize = 3;
+
+/// Finds config files in `search_dirs` that are for a name similar to `name`,
+/// in case gig was installed under the wrong name. These are returned with the
+/// name they're for, most similar first
+fn suggest_config_files(
+    search_dirs: &[&Path],
+    name: &str,
+) -> Vec<(PathBuf, String)> {
+    // Anything further off is more likely an unrelated file, like Cargo.toml
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut suggestions = search_dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|extension| {
+                CONFIG_EXTENSIONS.iter().any(|known| extension == *known)
+            }) && path.is_file()
+        })
+        .filter_map(|path| {
+            let other = path.file_stem()?.to_str()?.to_owned();
+            let distance = edit_distance(&other, name);
+            (distance <= max_distance).then_some((distance, path, other))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so earlier search dirs still win ties
+    suggestions.sort_by_key(|(distance, ..)| *distance);
+    suggestions
+        .into_iter()
+        .take(MAX_CONFIG_SUGGESTIONS)
+        .map(|(_, path, other)| (path, other))
+        .collect()
+}
+
+/// The Levenshtein distance between `a` and `b`, ignoring case
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current.push(
+                substitution.min(previous[j + 1] + 1).min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Runs each fallback in turn until one can be started, returning its status.
+/// A fallback that starts but fails is still returned as-is
+/// Fallbacks with `confirm` set are only run if the user agrees to, having
+/// been told the `reason` for running them
+fn run_fallbacks<'a>(
+    fallbacks: impl Iterator<Item = (&'a Fallback, anyhow::Result<LoggedCommand>)>,
+    reason: &str,
+    unresolved: &AtomicUsize,
+    mut before_fallback: impl FnMut(
+        &Fallback,
+        &mut LoggedCommand,
+    ) -> anyhow::Result<()>,
+) -> anyhow::Result<ExitStatus> {
+    let mut fallbacks = fallbacks.peekable();
+    while let Some((fallback, command)) = fallbacks.next() {
+        let mut command = match command {
+            Ok(command) => command,
+            Err(why) if fallbacks.peek().is_some() => {
+                warn!("{why:#}, trying next fallback");
+                continue;
+            },
+            Err(why) => return Err(why),
+        };
+        if fallback.confirm
+            && !*DRY_RUN
+            && !confirm(&format!("{reason}, run {command}?"))?
+        {
+            bail!("declined to run fallback");
+        }
+        before_fallback(fallback, &mut command)?;
+        match command.status(unresolved) {
+            // io::Errors come from failing to start the fallback (e.g. it's
+            // not installed), whereas timeouts etc. are bail!-ed
+            Err(why)
+                if fallbacks.peek().is_some()
+                    && why.downcast_ref::<io::Error>().is_some() =>
+            {
+                warn!("{why:#}, trying next fallback");
+            },
+            result => return result,
+        }
+    }
+    bail!("no fallbacks to run")
+}
+
+#[derive(Debug)]
+struct Fallback {
+    /// If not given, the program is re-run without get-it-going in $PATH
+    target: Option<FallbackTarget>,
+    confirm: bool,
+    /// How `command` is split into arguments
+    shell: CommandSyntax,
+    /// Run the `before_run` steps first (in CWD) if the root wasn't found
+    run_before: bool,
+}
+
+#[derive(Debug)]
+enum FallbackTarget {
+    Path(PathBuf),
+    Command(String),
+    /// Program names, the first of which found in $PATH is run
+    Candidates(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for Fallback {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FallbackVisitor;
+
+        impl<'de> Visitor<'de> for FallbackVisitor {
+            type Value = Fallback;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("fallback table")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut target = None;
+                let mut confirm = false;
+                let mut shell = CommandSyntax::default();
+                let mut run_before = false;
+                while let Some(key) = map.next_key::<String>()? {
+                    let new_target = match key.as_str() {
+                        "shell" => {
+                            shell = map.next_value::<CommandSyntax>()?;
+                            continue;
+                        },
+                        "run_before" => {
+                            run_before = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "confirm" => {
+                            confirm = map.next_value::<bool>()?;
+                            continue;
+                        },
+                        "path" => {
+                            let value = map.next_value::<String>()?;
+                            FallbackTarget::Path(
+                                expand_path(&value)
+                                    .map_err(A::Error::custom)?,
+                            )
+                        },
+                        "command" => {
+                            let value = map.next_value::<String>()?;
+                            if value.is_empty() {
+                                return Err(A::Error::custom(
+                                    "command can't be empty",
+                                ));
+                            }
+                            FallbackTarget::Command(value)
+                        },
+                        "candidates" => {
+                            let value = map.next_value::<Vec<String>>()?;
+                            if value.is_empty() {
+                                return Err(A::Error::custom(
+                                    "candidates can't be empty",
+                                ));
+                            }
+                            FallbackTarget::Candidates(value)
+                        },
+                        unknown => {
+                            return Err(unrecognised_key(unknown, &[
+                                "path",
+                                "command",
+                                "candidates",
+                                "confirm",
+                                "shell",
+                                "run_before",
+                            ]))
+                        },
+                    };
+                    if target.replace(new_target).is_some() {
+                        return Err(A::Error::custom(
+                            "only one of \"path\", \"command\", or \
+                             \"candidates\" can be given",
+                        ));
+                    }
+                }
+                Ok(Fallback {
+                    target,
+                    confirm,
+                    shell,
+                    run_before,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(FallbackVisitor)
+    }
+}
+
+#[derive(Debug)]
+struct LoggedCommand {
+    command: Command,
+    timeout: Option<Duration>,
+    stdin: InputMode,
+    stdout: OutputMode,
+    stderr: OutputMode,
+    resolution: Resolution,
+    /// Variables whose values aren't displayed, as they could be secrets
+    redacted_env: Vec<String>,
+}
+
+/// Whether the program a [`LoggedCommand`] runs could be found when it was
+/// made. This is only checked for dry runs, as otherwise spawning it finds out
+#[derive(Debug, PartialEq)]
+enum Resolution {
+    Unchecked,
+    Found,
+    NotFound,
+    /// Not found, but it's relative to the root, so before_run could create it
+    NotBuiltYet,
+    NotExecutable(PathBuf),
+}
+
+impl Resolution {
+    /// Checks whether `command`'s program can be found, in its working
+    /// directory or the $PATH it's run with, and is executable
+    fn of(command: &Command) -> Self {
+        let program = Path::new(command.get_program());
+        let found = if program.components().count() > 1 {
+            let dir = command.get_current_dir().unwrap_or(&CWD);
+            Some(dir.join(program)).filter(|path| path.exists())
+        } else {
+            let set_path = command.get_envs().find_map(|(name, value)| {
+                let is_path = name == "PATH"
+                    || (cfg!(windows) && name.eq_ignore_ascii_case("PATH"));
+                is_path.then(|| value.map(OsStr::to_owned).unwrap_or_default())
+            });
+            let path = set_path.or_else(|| env::var_os("PATH"));
+            which(program, &path.unwrap_or_default())
+        };
+        match found {
+            None => Resolution::NotFound,
+            Some(found) if !is_executable(&found) => {
+                Resolution::NotExecutable(found)
+            },
+            Some(_) => Resolution::Found,
+        }
+    }
+
+    /// [`Resolution::of`] `command`, if this is a dry run
+    fn for_dry_run(command: &Command) -> Self {
+        if *DRY_RUN {
+            Resolution::of(command)
+        } else {
+            Resolution::Unchecked
+        }
+    }
+}
+
+impl LoggedCommand {
+    fn status(self, unresolved: &AtomicUsize) -> anyhow::Result<ExitStatus> {
+        self.output(unresolved).map(|output| output.status)
+    }
+
+    /// Sets `$name` to what a `before_run` step captured with `capture_into`,
+    /// which is never displayed as it's likely to be a token or the like
+    fn set_captured_env(&mut self, name: &str, value: &str) {
+        debug!("setting ${name} from before_run's stdout");
+        self.command.env(name, value);
+        self.redacted_env.push(name.to_owned());
+    }
+
+    /// Runs the command, also returning its stdout and stderr if they were
+    /// captured. In a dry run, it's counted in `unresolved` if it wouldn't be
+    /// able to run
+    fn output(
+        mut self,
+        unresolved: &AtomicUsize,
+    ) -> anyhow::Result<CommandOutput> {
+        if *DRY_RUN {
+            info!("would run: {self}");
+            let program = Path::new(self.command.get_program());
+            match &self.resolution {
+                Resolution::NotFound => {
+                    error!(
+                        "{} can't be found, so {self} would fail to run",
+                        program.display(),
+                    );
+                    unresolved.fetch_add(1, Ordering::Relaxed);
+                },
+                Resolution::NotExecutable(path) => {
+                    error!(
+                        "{} isn't executable, so {self} would fail to run",
+                        path.display(),
+                    );
+                    unresolved.fetch_add(1, Ordering::Relaxed);
+                },
+                Resolution::NotBuiltYet => warn!(
+                    "{} doesn't exist yet, so {self} would fail to run unless \
+                     before_run creates it",
+                    program.display(),
+                ),
+                Resolution::Unchecked | Resolution::Found => {},
+            }
+            return Ok(CommandOutput {
+                status: ExitStatus::from_raw(0),
+                stdout: None,
+                stderr: None,
+            });
+        }
+        info!("running: {self}");
+        // Set here so it isn't logged
+        set_depth(&mut self.command);
+        self.command.stdin(self.stdin.stdio()?);
+        self.command.stdout(self.stdout.stdio());
+        self.command.stderr(self.stderr.stdio());
+        #[cfg(unix)]
+        if *OWN_PROCESS_GROUP {
+            use std::os::unix::process::CommandExt;
+
+            self.command.process_group(0);
+        }
+        let start = Instant::now();
+        let mut child = self
+            .command
+            .spawn()
+            .with_context(|| format!("failed to invoke {self}"))?;
+        // Held until the child has been waited for
+        let _forwarding = forward_signals_to(&child);
+        // Only needed to kill everything the child started if it times out
+        #[cfg(windows)]
+        let job = self.timeout.and_then(|_| {
+            JobObject::containing(&child)
+                .map_err(|why| debug!("couldn't make a job object: {why}"))
+                .ok()
+        });
+        // Pipes must be read while waiting, otherwise the child could fill
+        // them up and block forever
+        let stdout = child.stdout.take().map(read_in_background);
+        let stderr = child.stderr.take().map(read_in_background);
+        let collect = |status| {
+            if *TIMING {
+                // Rounded, as nanoseconds would just be noise
+                let elapsed = start.elapsed().as_millis() as u64;
+                info!(
+                    "{self} ran for {}",
+                    humantime::format_duration(Duration::from_millis(elapsed)),
+                );
+            }
+            // The child has been reaped, but anything it left running could
+            // still have its stdout/stderr open, so they aren't waited on
+            // indefinitely
+            let finish = |read: BackgroundRead, name| {
+                let (output, complete) = read.finish();
+                if !complete {
+                    warn!(
+                        "stopped waiting for the rest of {self}'s {name}, as \
+                         something it started is keeping it open"
+                    );
+                }
+                output
+            };
+            let stdout = stdout.map(|stdout| finish(stdout, "stdout"));
+            // Not logged, as it's captured to be a value for capture_into
+            if let Some(stdout) = &stdout {
+                debug!("captured {} bytes of stdout from {self}", stdout.len());
+            }
+            let stderr = stderr.map(|stderr| finish(stderr, "stderr"));
+            Ok(CommandOutput {
+                status,
+                stdout,
+                stderr,
+            })
+        };
+
+        let Some(timeout) = self.timeout else {
+            let status = child
+                .wait()
+                .with_context(|| format!("failed to wait for {self}"))?;
+            return collect(status);
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = child
+                .try_wait()
+                .with_context(|| format!("failed to wait for {self}"))?
+            {
+                return collect(status);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(50)));
+        }
+        // Everything the child started is killed too if it can be, so nothing
+        // is left running (or holding its pipes open). In the foreground of a
+        // terminal, the child shares gig's process group, so only it's killed
+        #[cfg(unix)]
+        let killed = if *OWN_PROCESS_GROUP {
+            // SAFETY: kill has no preconditions. The child hasn't been reaped,
+            // so its PID (and group ID) can't have been reused
+            match unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) } {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            }
+        } else {
+            child.kill()
+        };
+        #[cfg(windows)]
+        let killed = match &job {
+            Some(job) => job.terminate(),
+            None => child.kill(),
+        };
+        if let Err(why) = killed {
+            warn!("failed to kill {self}: {why}");
+        }
+        // Reap the child so it doesn't stick around as a zombie
+        let _ = child.wait();
+        bail!(
+            "{self} timed out after {}",
+            humantime::format_duration(timeout),
+        )
+    }
+}
+
+/// How a [`LoggedCommand`] exited, with any output that was captured
+struct CommandOutput {
+    status: ExitStatus,
+    stdout: Option<String>,
+    stderr: Option<String>,
+}
+
+/// The most children that signals can be forwarded to at once
+#[cfg(unix)]
+const MAX_FORWARDED_CHILDREN: usize = 32;
+
+/// Where signals are forwarded to for each running child, as given to kill: its
+/// PID, or its process group's ID negated if it has its own. 0 is for unused
+/// slots. Atomics are used so they can be read in the signal handler
+#[cfg(unix)]
+static FORWARDED_CHILDREN: [AtomicI32; MAX_FORWARDED_CHILDREN] =
+    [const { AtomicI32::new(0) }; MAX_FORWARDED_CHILDREN];
+
+/// Whether children are put in a process group of their own. They can't be
+/// while gig is in the foreground of a terminal, as only the foreground group
+/// can read from it and gets signals like Ctrl+C from it. Otherwise (e.g. when
+/// run by an IDE or a service manager), a group of their own means signals
+/// sent to gig's whole group aren't received twice, and timeouts can kill
+/// everything the child started
+#[cfg(unix)]
+static OWN_PROCESS_GROUP: Lazy<bool> = Lazy::new(|| {
+    let Ok(tty) = fs::File::open("/dev/tty") else {
+        return true;
+    };
+    // SAFETY: tcgetpgrp and getpgrp have no preconditions, and tty is open
+    let foreground = unsafe {
+        libc::tcgetpgrp(std::os::fd::AsRawFd::as_raw_fd(&tty))
+            == libc::getpgrp()
+    };
+    !foreground
+});
+
+/// How many children are running, during which gig ignores Ctrl+C
+#[cfg(windows)]
+static RUNNING_CHILDREN: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALL_SIGNAL_HANDLERS: Once = Once::new();
+
+/// Stops forwarding signals to a child when dropped
+struct SignalForwarding {
+    #[cfg(unix)]
+    slot: Option<usize>,
+}
+
+impl Drop for SignalForwarding {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(slot) = self.slot {
+            FORWARDED_CHILDREN[slot].store(0, Ordering::SeqCst);
+        }
+        #[cfg(windows)]
+        RUNNING_CHILDREN.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Makes gig pass on SIGINT and SIGTERM to `child` while it's running, instead
+/// of exiting and leaving it orphaned, so its exit status is still returned.
+/// If the child has its own process group (see [`OWN_PROCESS_GROUP`]), every
+/// signal is forwarded to the whole group, as nothing else reaches it. If it
+/// shares gig's, signals from the terminal (e.g. Ctrl+C) already go to it, so
+/// only signals sent to gig by another process are forwarded. That can't tell
+/// a signal sent just to gig apart from one sent to its whole group, so the
+/// latter reaches the child twice, but only while gig is in the foreground of
+/// a terminal
+///
+/// On Windows, Ctrl+C and Ctrl+Break go to every process attached to the
+/// console, whichever process sent them, so the child always gets them
+/// exactly once and gig just ignores them while it's running
+fn forward_signals_to(child: &Child) -> SignalForwarding {
+    INSTALL_SIGNAL_HANDLERS.call_once(install_signal_handlers);
+    #[cfg(unix)]
+    {
+        let pid = child.id() as i32;
+        let target = if *OWN_PROCESS_GROUP { -pid } else { pid };
+        let slot = FORWARDED_CHILDREN.iter().position(|slot| {
+            slot.compare_exchange(0, target, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        if slot.is_none() {
+            debug!(
+                "too many children running, not forwarding signals to {pid}"
+            );
+        }
+        SignalForwarding { slot }
+    }
+    #[cfg(windows)]
+    {
+        let _ = child;
+        RUNNING_CHILDREN.fetch_add(1, Ordering::SeqCst);
+        SignalForwarding {}
+    }
+}
+
+#[cfg(unix)]
+fn install_signal_handlers() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: sigaction is zeroable, and handle_signal has the signature
+        // SA_SIGINFO needs. The handler only uses async-signal-safe functions
+        // and atomics
+        let installed = unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction =
+                handle_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+        if installed != 0 {
+            warn!(
+                "couldn't handle signal {signal}, so it won't be forwarded: {}",
+                io::Error::last_os_error(),
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    // SAFETY: with SA_SIGINFO, info points to a valid siginfo_t. si_pid is set
+    // for signals sent with kill, and 0 for those from the terminal
+    let from_process = unsafe { (*info).si_pid() } != 0;
+    let mut any_children = false;
+    for slot in &FORWARDED_CHILDREN {
+        let target = slot.load(Ordering::SeqCst);
+        if target != 0 {
+            any_children = true;
+            // A child with its own group (a negative target) doesn't get
+            // signals from the terminal, nor ones sent to gig's group
+            if from_process || target < 0 {
+                // SAFETY: kill is async-signal-safe
+                unsafe { libc::kill(target, signal) };
+            }
+        }
+    }
+    // Without a child to wait for, gig should do what it would have done if
+    // it didn't handle the signal
+    if !any_children {
+        // SAFETY: signal and raise are async-signal-safe
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn install_signal_handlers() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+    }
+
+    /// Returning non-zero marks the event as handled, stopping gig exiting
+    unsafe extern "system" fn handle_ctrl(_ctrl_type: u32) -> i32 {
+        (RUNNING_CHILDREN.load(Ordering::SeqCst) > 0) as i32
+    }
+
+    // SAFETY: handle_ctrl has the signature of a HandlerRoutine, and only
+    // reads an atomic
+    if unsafe { SetConsoleCtrlHandler(Some(handle_ctrl), 1) } == 0 {
+        warn!(
+            "couldn't handle Ctrl+C, so gig may exit before the program does: \
+             {}",
+            io::Error::last_os_error(),
+        );
+    }
+}
+
+/// A job object a child has been put in, so everything it starts (which is put
+/// in the job too) can be killed with it. Processes the child started before
+/// it was put in the job aren't in it, though it's done straight after
+/// spawning. Closing the job doesn't kill what's in it
+#[cfg(windows)]
+struct JobObject(*mut std::ffi::c_void);
+
+#[cfg(windows)]
+impl JobObject {
+    fn containing(child: &Child) -> io::Result<Self> {
+        use std::{ffi::c_void, os::windows::io::AsRawHandle};
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn CreateJobObjectW(
+                attributes: *mut c_void,
+                name: *const u16,
+            ) -> *mut c_void;
+            fn AssignProcessToJobObject(
+                job: *mut c_void,
+                process: *mut c_void,
+            ) -> i32;
+        }
+
+        // SAFETY: null attributes and name are allowed, giving an unnamed job
+        // with default security
+        let handle =
+            unsafe { CreateJobObjectW(std::ptr::null_mut(), std::ptr::null()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let job = JobObject(handle);
+        // SAFETY: both handles are open, as the child hasn't been waited for
+        if unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle()) }
+            == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(job)
+    }
+
+    /// Kills everything in the job
+    fn terminate(&self) -> io::Result<()> {
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn TerminateJobObject(
+                job: *mut std::ffi::c_void,
+                exit_code: u32,
+            ) -> i32;
+        }
+
+        // SAFETY: the handle is open until the job is dropped. The exit code
+        // is the same as Child::kill uses
+        match unsafe { TerminateJobObject(self.0, 1) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn CloseHandle(handle: *mut std::ffi::c_void) -> i32;
+        }
+
+        // SAFETY: the handle is open, and isn't used after this
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Whether gig is running as root, going by the effective user ID, as that's
+/// what privileges are checked against
+#[cfg(unix)]
+fn is_elevated() -> io::Result<bool> {
+    // SAFETY: geteuid has no preconditions and can't fail
+    Ok(unsafe { libc::geteuid() } == 0)
+}
+
+/// Whether gig is running with an elevated token, i.e. "as administrator"
+#[cfg(windows)]
+fn is_elevated() -> io::Result<bool> {
+    use std::ffi::c_void;
+
+    const TOKEN_QUERY: u32 = 0x0008;
+    const TOKEN_ELEVATION: i32 = 20;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn OpenProcessToken(
+            process: *mut c_void,
+            desired_access: u32,
+            token: *mut *mut c_void,
+        ) -> i32;
+        fn GetTokenInformation(
+            token: *mut c_void,
+            class: i32,
+            information: *mut c_void,
+            length: u32,
+            return_length: *mut u32,
+        ) -> i32;
+    }
+
+    let mut token = std::ptr::null_mut();
+    // SAFETY: GetCurrentProcess returns a pseudo-handle that doesn't need
+    // closing, and token is a valid place to write the opened handle to
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }
+        == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    // TOKEN_ELEVATION is a single DWORD, non-zero if elevated
+    let mut elevation = 0u32;
+    let mut length = 0;
+    // SAFETY: token was opened with TOKEN_QUERY, and elevation is the size
+    // given, matching what TokenElevation writes
+    let queried = unsafe {
+        GetTokenInformation(
+            token,
+            TOKEN_ELEVATION,
+            (&mut elevation as *mut u32).cast(),
+            size_of::<u32>() as u32,
+            &mut length,
+        )
+    };
+    let result = if queried == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(elevation != 0)
+    };
+    // SAFETY: token is an open handle that isn't used after this
+    unsafe { CloseHandle(token) };
+    result
+}
+
+/// The highest priority niceness, which needs root (or CAP_SYS_NICE) to use
+const NICE_MIN: i64 = -20;
+/// The lowest priority niceness
+const NICE_MAX: i64 = 19;
+
+/// Makes `command` run with the given niceness, which is set in the child
+/// just before the program is executed
+#[cfg(unix)]
+fn set_nice(command: &mut Command, nice: i32) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: setpriority is async-signal-safe, and nothing is allocated
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                // The error is from the child, so spawning reports it
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Makes `command` run with the priority class closest to the given niceness,
+/// as Windows doesn't have niceness itself
+#[cfg(windows)]
+fn set_nice(command: &mut Command, nice: i32) {
+    const IDLE_PRIORITY_CLASS: u32 = 0x40;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+    const NORMAL_PRIORITY_CLASS: u32 = 0x20;
+    const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x8000;
+    const HIGH_PRIORITY_CLASS: u32 = 0x80;
+
+    let class = match nice {
+        15.. => IDLE_PRIORITY_CLASS,
+        1..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+        0 => NORMAL_PRIORITY_CLASS,
+        -14..=-1 => ABOVE_NORMAL_PRIORITY_CLASS,
+        ..=-15 => HIGH_PRIORITY_CLASS,
+    };
+    command.creation_flags(class);
+}
+
+#[cfg(unix)]
+fn set_arg0(command: &mut Command, arg0: &str) {
+    use std::os::unix::process::CommandExt;
+
+    command.arg0(arg0);
+}
+
+#[cfg(not(unix))]
+fn set_arg0(_: &mut Command, _: &str) {
+    debug!("ignoring run.arg0, as it's only supported on Unix");
+}
+
+/// Parses a umask given as octal, e.g. "022"
+fn parse_umask(umask: &str) -> anyhow::Result<u16> {
+    u16::from_str_radix(umask, 8)
+        .ok()
+        .filter(|mask| *mask <= 0o777 && !umask.starts_with('+'))
+        .ok_or_else(|| {
+            anyhow!(
+                "umask should be octal from \"000\" to \"777\", not {umask:?}"
+            )
+        })
+}
+
+/// Makes `command` run with the given umask, which is set in the child just
+/// before the program is executed
+#[cfg(unix)]
+fn set_umask(command: &mut Command, umask: u16) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: umask is async-signal-safe, and always succeeds
+    unsafe {
+        command.pre_exec(move || {
+            libc::umask(libc::mode_t::from(umask));
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn set_umask(_: &mut Command, _: u16) {
+    debug!("ignoring run.umask, as it's only supported on Unix");
+}
+
+/// How long to wait for the rest of a child's captured output once it's
+/// exited, as anything it started in the background could hold the pipe open
+const PIPE_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Output being read from a pipe by [`read_in_background`]
+struct BackgroundRead {
+    output: Arc<Mutex<Vec<u8>>>,
+    /// Disconnects once the pipe has been read to the end
+    done: mpsc::Receiver<()>,
+}
+
+impl BackgroundRead {
+    /// Returns everything read from the pipe, waiting up to
+    /// [`PIPE_DRAIN_TIMEOUT`] for the rest of it. Also returns whether it was
+    /// read to the end
+    fn finish(self) -> (String, bool) {
+        let complete = matches!(
+            self.done.recv_timeout(PIPE_DRAIN_TIMEOUT),
+            Err(mpsc::RecvTimeoutError::Disconnected),
+        );
+        let output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        (String::from_utf8_lossy(&output).into_owned(), complete)
+    }
+}
+
+/// Reads all of `pipe` on another thread, so it can't fill up while the
+/// process writing to it is waited on. If the pipe is never closed, the thread
+/// is left blocked reading it
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> BackgroundRead {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let (done_tx, done) = mpsc::channel();
+    let reading = Arc::clone(&output);
+    thread::spawn(move || {
+        // Dropped when this returns, which is what's waited for
+        let _done_tx = done_tx;
+        let mut buffer = [0; 8192];
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => reading
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(&buffer[..read]),
+                Err(why) if why.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) => break,
+            }
+        }
+    });
+    BackgroundRead { output, done }
+}
+
+/// What to do with a command's stdout or stderr
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OutputMode {
+    /// Output to the same place as get-it-going
+    #[default]
+    Inherit,
+    /// Discard
+    Null,
+    /// Hide, but keep for error messages
+    Capture,
+}
+
+impl OutputMode {
+    fn stdio(self) -> Stdio {
+        match self {
+            OutputMode::Inherit => Stdio::inherit(),
+            OutputMode::Null => Stdio::null(),
+            OutputMode::Capture => Stdio::piped(),
+        }
+    }
+}
+
+/// Where a command's stdin comes from
+#[derive(Debug, Default, Clone)]
+enum InputMode {
+    /// The same place as get-it-going's
+    #[default]
+    Inherit,
+    /// Nowhere, so reading it gets EOF
+    Null,
+    File(PathBuf),
+}
+
+impl InputMode {
+    fn stdio(&self) -> anyhow::Result<Stdio> {
+        Ok(match self {
+            InputMode::Inherit => Stdio::inherit(),
+            InputMode::Null => Stdio::null(),
+            InputMode::File(path) => fs::File::open(path)
+                .with_context(|| {
+                    format!("couldn't open {} for stdin", path.display())
+                })?
+                .into(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for InputMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct InputModeVisitor;
+
+        impl Visitor<'_> for InputModeVisitor {
+            type Value = InputMode;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("\"inherit\", \"null\", or a path")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                Ok(match value {
+                    "inherit" => InputMode::Inherit,
+                    "null" => InputMode::Null,
+                    "" => return Err(E::custom("stdin can't be empty")),
+                    path => {
+                        InputMode::File(expand_path(path).map_err(E::custom)?)
+                    },
+                })
+            }
+        }
+
+        deserializer.deserialize_str(InputModeVisitor)
+    }
+}
+
+impl fmt::Display for LoggedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Not necessarily UTF-8, as it can have the root joined onto it
+        let program = self.command.get_program().to_string_lossy();
+        let args = self
+            .command
+            .get_args()
+            .map(OsStr::to_string_lossy)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let cwd = self
+            .command
+            .get_current_dir()
+            .filter(|cwd| *cwd != *CWD)
+            .map_or(String::new(), |cwd| format!(" in {}", cwd.display()));
+        // Only variables that have been changed from gig's own environment
+        // are listed by get_envs
+        let mut set_vars = String::new();
+        let mut removed_vars = Vec::new();
+        for (name, value) in self.command.get_envs() {
+            let name = name.to_string_lossy();
+            match value {
+                Some(_) if self.redacted_env.iter().any(|var| *var == name) => {
+                    set_vars.push_str(&format!("{name}=<redacted> "));
+                },
+                Some(value) => {
+                    set_vars.push_str(&format!(
+                        "{name}={} ",
+                        value.to_string_lossy(),
+                    ));
+                },
+                None => removed_vars.push(name),
+            }
+        }
+        let removed_vars = if !removed_vars.is_empty() {
+            format!(" without {}", removed_vars.join(", "))
+        } else {
+            String::new()
+        };
+        let stdin = match &self.stdin {
+            InputMode::File(path) => {
+                format!(" with stdin from {}", path.display())
+            },
+            _ => String::new(),
+        };
+        write!(
+            f,
+            "`{set_vars}{program}{space}{args}`{cwd}{removed_vars}{stdin}",
+            space = if !args.is_empty() { " " } else { "" },
+        )
+    }
+}
+
+/// Directories not searched by `search_children_depth`, as they're large and
+/// shouldn't contain the root
+const SKIPPED_CHILD_DIRECTORIES: &[&str] = &["node_modules", "target"];
+
+/// The `GIG_CONFIG` that means the config is read from stdin. Paths relative to
+/// the config are then relative to CWD
+const STDIN_CONFIG: &str = "-";
+
+/// The variable holding the user's home directory, which `~` expands to
+const HOME_VAR: &str = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+
+/// The asdf file pinning tool versions, read from the root for
+/// `run.tool_versions`
+const TOOL_VERSIONS_FILE: &str = ".tool-versions";
+
+/// The platforms that can have override tables in a config file, named as in
+/// [`env::consts::OS`]
+const PLATFORMS: [&str; 3] = ["windows", "macos", "linux"];
+
+fn default_required_extensions_depth() -> usize {
+    5
+}
+
+fn default_root_env_var() -> String {
+    String::from("GIG_ROOT")
+}
+
+/// Reads `config_files` into one table, with each overriding the ones after
+/// it, and the selected profile applied. This is the config as it's loaded,
+/// before being deserialised
+fn merged_table(config_files: &[PathBuf]) -> anyhow::Result<toml::Table> {
+    let Some((config_file, bases)) = config_files.split_first() else {
+        bail!("no config files given");
+    };
+    let mut config = toml::Table::new();
+    for base in bases.iter().rev() {
+        debug!("merging over {}", base.display());
+        merge_tables(&mut config, load_table(base, &mut Vec::new())?);
+    }
+    merge_tables(&mut config, load_table(config_file, &mut Vec::new())?);
+    apply_profile(&mut config, PROFILE.as_deref())?;
+    Ok(config)
+}
+
+/// Lists `config_files` as they're merged, for errors
+fn joined(config_files: &[PathBuf]) -> String {
+    config_files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// Works out which of `config_files` an error from deserialising their merged
+/// table came from, and where in it, as the merged table has no locations.
+/// Each TOML file is deserialised on its own, and one failing with the same
+/// error is where the error is. Otherwise, all the files are named
+fn locate_error(
+    config_files: &[PathBuf],
+    why: toml::de::Error,
+) -> anyhow::Error {
+    for config_file in config_files {
+        let Ok(config) = fs::read_to_string(config_file) else {
+            continue;
+        };
+        // Other formats fail to parse, so won't have the same error
+        let Err(located) = toml::from_str::<AppConfig>(&config) else {
+            continue;
+        };
+        debug!("re-parsed {} to locate error", config_file.display());
+        if located.span().is_some() && located.message() == why.message() {
+            return anyhow::Error::new(located)
+                .context(format!("invalid config {}", config_file.display()));
+        }
+    }
+    anyhow::Error::new(why)
+        .context(format!("invalid config {}", joined(config_files)))
+}
+
+/// The config file for [`NAME`] in `dir`, if there is one. If there are config
+/// files in multiple formats, the first of [`CONFIG_EXTENSIONS`] is used
+fn config_file_in(dir: &Path) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS.iter().find_map(|extension| {
+        let config_file = dir.join(format!("{}.{extension}", &*NAME));
+        debug!("checking if {} exists", config_file.display());
+        if config_file.exists() {
+            info!("found {}", config_file.display());
+            Some(config_file)
+        } else {
+            None
+        }
+    })
+}
+
+/// Reads `config_file` into a table, with any platform overrides applied, and
+/// merged over the config it `extends` if it has one. `chain` holds the files
+/// that extend this one, to detect cycles
+fn load_table(
+    config_file: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> anyhow::Result<toml::Table> {
+    let config = if config_file == Path::new(STDIN_CONFIG) {
+        let mut config = String::new();
+        io::stdin()
+            .read_to_string(&mut config)
+            .context("couldn't read config from stdin")?;
+        config
+    } else {
+        fs::read_to_string(config_file).with_context(|| {
+            format!("couldn't read {}", config_file.display())
+        })?
+    };
+    // Other formats are parsed into a TOML table, so they can be handled the
+    // same from here on
+    let parse = || -> anyhow::Result<toml::Table> {
+        Ok(match config_file.extension().and_then(OsStr::to_str) {
+            #[cfg(feature = "json")]
+            Some("json") => serde_json::from_str(&config)?,
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => serde_norway::from_str(&config)?,
+            _ => toml::from_str(&config)?,
+        })
+    };
+    let mut config = parse()
+        .with_context(|| format!("couldn't parse {}", config_file.display()))?;
+    // Overrides for other platforms are discarded, and the active one is
+    // merged over the top of the base config
+    let mut platform_override = None;
+    for platform in PLATFORMS {
+        match config.remove(platform) {
+            Some(toml::Value::Table(table)) if platform == env::consts::OS => {
+                platform_override = Some(table);
+            },
+            Some(toml::Value::Table(_)) | None => {},
+            Some(_) => bail!("[{platform}] should be a table"),
+        }
+    }
+    if let Some(platform_override) = platform_override {
+        debug!("applying [{}] overrides", env::consts::OS);
+        merge_tables(&mut config, platform_override);
+    }
+    read_required_files_manifest(&mut config, config_file)?;
+
+    let base_file = match config.remove("extends") {
+        Some(toml::Value::String(base_file)) => base_file,
+        Some(_) => bail!("extends should be a path"),
+        None => return Ok(config),
+    };
+    let base_file = config_file
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(expand_path(&base_file)?);
+    // Paths are canonicalised so different ways of referring to the same file
+    // are still detected as a cycle
+    let canonical_file = fs::canonicalize(config_file)
+        .unwrap_or_else(|_| config_file.to_owned());
+    chain.push(canonical_file);
+    let canonical_base = fs::canonicalize(&base_file)
+        .with_context(|| format!("couldn't read {}", base_file.display()))?;
+    if chain.contains(&canonical_base) {
+        bail!(
+            "{} extends itself (through {})",
+            base_file.display(),
+            chain
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+        );
+    }
+    debug!("{} extends {}", config_file.display(), base_file.display());
+    let mut base = load_table(&base_file, chain)?;
+    merge_tables(&mut base, config);
+    Ok(base)
+}
+
+/// Removes `[profiles]` from `config`, merging the one named `profile` over the
+/// rest of the config if one is given
+fn apply_profile(
+    config: &mut toml::Table,
+    profile: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut profiles = match config.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => bail!("[profiles] should be a table"),
+        None => toml::Table::new(),
+    };
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+    match profiles.remove(profile) {
+        Some(toml::Value::Table(overrides)) => {
+            debug!("applying profile {profile}");
+            merge_tables(config, overrides);
+            Ok(())
+        },
+        Some(_) => bail!("[profiles.{profile}] should be a table"),
+        None if profiles.is_empty() => {
+            bail!("GIG_PROFILE is set to {profile}, but there are no profiles")
+        },
+        None => bail!(
+            "GIG_PROFILE is set to {profile}, which isn't one of the profiles \
+             ({})",
+            profiles.keys().cloned().collect::<Vec<_>>().join(", "),
+        ),
+    }
+}
+
+/// Replaces `required_files = { from = "<path>" }` in `config` with the list of
+/// required files in that file, which is relative to `config_file`. Each line
+/// is an entry, ignoring blank lines and those starting with `#`
+fn read_required_files_manifest(
+    config: &mut toml::Table,
+    config_file: &Path,
+) -> anyhow::Result<()> {
+    let Some(toml::Value::Table(table)) = config.get("required_files") else {
+        return Ok(());
+    };
+    let mut from = None;
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("from", toml::Value::String(path)) => from = Some(path),
+            ("from", _) => bail!("required_files.from should be a path"),
+            (unknown, _) => bail!(
+                "unrecognised key \"{unknown}\" in required_files, expected \
+                 \"from\""
+            ),
+        }
+    }
+    let Some(from) = from else {
+        bail!("required_files should be a list, or a table with from");
+    };
+    let manifest = config_file
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(expand_path(from)?);
+    let contents = fs::read_to_string(&manifest)
+        .with_context(|| format!("couldn't read {}", manifest.display()))?;
+    debug!("reading required files from {}", manifest.display());
+    let required_files = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| toml::Value::String(line.to_owned()))
+        .collect();
+    config.insert(
+        String::from("required_files"),
+        toml::Value::Array(required_files),
+    );
+    Ok(())
+}
+
+/// The keys that say what to run in the tables with these names, only one of
+/// which can be given
+const TARGET_KEYS: &[(&str, &[&str])] = &[
+    ("run", &[
+        "subcommand_of",
+        "path",
+        "via",
+        "shell",
+        "dispatch",
+    ]),
+    ("fallback", &["path", "command", "candidates"]),
+];
+
+/// Merges `overrides` into `base`. Tables present in both are merged
+/// recursively, any other value in `overrides` replaces the one in `base`.
+/// If a table's overrides give one of its [`TARGET_KEYS`], the base's other
+/// target keys are removed, so the override replaces what's run instead of
+/// conflicting with it
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                let targets = TARGET_KEYS
+                    .iter()
+                    .find(|(table, _)| *table == key)
+                    .map_or(&[][..], |(_, targets)| targets);
+                if targets.iter().any(|target| overrides.contains_key(*target))
+                {
+                    base.retain(|key, _| {
+                        !targets.contains(&key) || overrides.contains_key(key)
+                    });
+                }
+                merge_tables(base, overrides);
+            },
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
+/// Expands the variables in `value`, getting them from `var` (usually
+/// [`env::var`]). Supports `$VAR` and `${VAR}`, as well as `%VAR%` on Windows.
+/// `$$` (or `%%` on Windows) can be used to write a literal `$` (or `%`)
+fn expand_vars_with(
+    value: &str,
+    var: impl Fn(&str) -> Result<String, env::VarError>,
+) -> anyhow::Result<String> {
+    let lookup = |name: &str| match var(name) {
+        Ok(value) => Ok(value),
+        Err(env::VarError::NotPresent) => {
+            Err(anyhow!("${name} is not set (in \"{value}\")"))
+        },
+        Err(env::VarError::NotUnicode(_)) => {
+            Err(anyhow!("${name} is not valid UTF-8 (in \"{value}\")"))
+        },
+    };
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) =
+        rest.find(|c| c == '$' || (cfg!(windows) && c == '%'))
+    {
+        expanded.push_str(&rest[..index]);
+        let sigil = &rest[index..=index];
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix(sigil) {
+            // Escaped
+            expanded.push_str(sigil);
+            rest = after;
+        } else if sigil == "%" {
+            match rest.split_once('%') {
+                Some((name, after)) if !name.is_empty() => {
+                    expanded.push_str(&lookup(name)?);
+                    rest = after;
+                },
+                // Not a variable, so leave it alone
+                _ => expanded.push('%'),
+            }
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let (name, after) = after
+                .split_once('}')
+                .ok_or_else(|| anyhow!("unclosed ${{ in \"{value}\""))?;
+            expanded.push_str(&lookup(name)?);
+            rest = after;
+        } else {
+            let name_len =
+                rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            if name_len == 0 {
+                // Lone $, leave it alone
+                expanded.push('$');
+            } else {
+                expanded.push_str(&lookup(&rest[..name_len])?);
+                rest = &rest[name_len..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands environment variables in `value` (see [`expand_vars_with`]), as well
+/// as a leading `~` into the user's home directory
+fn expand_path(value: &str) -> anyhow::Result<PathBuf> {
+    expand_path_with(value, |name| env::var(name), env::var_os(HOME_VAR))
+}
+
+/// [`expand_path`], getting variables from `var` and using `home` as the home
+/// directory, instead of getting them from the environment
+fn expand_path_with(
+    value: &str,
+    var: impl Fn(&str) -> Result<String, env::VarError>,
+    home: Option<OsString>,
+) -> anyhow::Result<PathBuf> {
+    let is_sep = |c: char| c == '/' || (cfg!(windows) && c == '\\');
+    let expanded = expand_vars_with(value, var)?;
+    match expanded.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(is_sep) => {
+            let home = home.ok_or_else(|| {
+                anyhow!("can't expand ~ as ${HOME_VAR} is not set")
+            })?;
+            // Can't use Path::join here as rest starts with a separator
+            let mut path = home;
+            path.push(rest);
+            Ok(path.into())
+        },
+        _ => Ok(expanded.into()),
+    }
+}
+
+/// Makes each ASCII letter in glob `pattern` (outside of `[...]`) a class
+/// matching either case of it, e.g. `Src/*.rs` becomes
+/// `[sS][rR][cC]/*.[rR][sS]`
+fn either_case_pattern(pattern: &str) -> String {
+    let mut either_case = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                either_case.push(c);
+                // ] straight after [ or [! is part of the class
+                if let Some(negate) = chars.next_if_eq(&'!') {
+                    either_case.push(negate);
+                }
+                if let Some(bracket) = chars.next_if_eq(&']') {
+                    either_case.push(bracket);
+                }
+                for c in chars.by_ref() {
+                    either_case.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            },
+            c if c.is_ascii_alphabetic() => {
+                either_case.push('[');
+                either_case.push(c.to_ascii_lowercase());
+                either_case.push(c.to_ascii_uppercase());
+                either_case.push(']');
+            },
+            c => either_case.push(c),
+        }
+    }
+    either_case
+}
+
+/// Finds `path` within `dir`, ignoring the case of each component. Names that
+/// match exactly are checked first, so the directory only has to be read if
+/// the case differs
+fn find_case_insensitive(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let mut found = dir.to_path_buf();
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            found.push(component);
+            continue;
+        };
+        let exact = found.join(name);
+        if exact.exists() {
+            found = exact;
+            continue;
+        }
+        let name = name.to_str()?.to_lowercase();
+        let entry = fs::read_dir(&found).ok()?.flatten().find(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|entry| entry.to_lowercase() == name)
+        })?;
+        trace!("{} matched {}", path.display(), entry.path().display());
+        found = entry.path();
+    }
+    Some(found)
+}
+
+/// Gets the directory to run a command in, being `working_directory` relative
+/// to `root` if given. Errors if this directory doesn't exist
+fn working_directory<'a>(
+    root: &'a Path,
+    working_directory: Option<&Path>,
+) -> anyhow::Result<Cow<'a, Path>> {
+    let Some(working_directory) = working_directory else {
+        return Ok(root.into());
+    };
+    let dir = root.join(working_directory);
+    if !dir.is_dir() {
+        bail!("working_directory {} doesn't exist", dir.display());
+    }
+    Ok(dir.into())
+}
+
+/// Makes sure a relative `program` path is still relative to `base` (the root,
+/// unless `paths_relative_to` says otherwise) when run from a different
+/// `working_directory`. Bare names are left as-is to be searched for in $PATH
+fn relative_to<'a>(
+    program: &'a Path,
+    base: &Path,
+    working_directory: &Path,
+) -> Cow<'a, Path> {
+    if working_directory != base
+        && program.is_relative()
+        && program.components().count() > 1
+    {
+        base.join(program).into()
+    } else {
+        program.into()
+    }
+}
+
+/// Asks the user a yes/no `question`, defaulting to no. If stdin isn't a
+/// terminal there's nobody to ask, so the answer is yes
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        debug!("stdin isn't a terminal, not asking \"{question}\"");
+        return Ok(true);
+    }
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+/// Removes `dir` from the list of directories in `path` (a $PATH value)
+fn remove_from_path(path: &OsStr, dir: &Path) -> OsString {
+    let dirs = env::split_paths(path).filter(|path_dir| {
+        let matches = if cfg!(windows) {
+            // Windows has to be case insensitive
+            path_dir.as_os_str().eq_ignore_ascii_case(dir)
+        } else {
+            path_dir == dir
+        };
+        if matches {
+            debug!("removing {} from $PATH", path_dir.display());
+        }
+        !matches
+    });
+    // Every directory came from a valid $PATH, so must be joinable again
+    env::join_paths(dirs).expect("directories from $PATH should be joinable")
+}
+
+/// Gets the modification time of `dir` as a duration since the Unix epoch
+fn dir_mtime(dir: &Path) -> Option<Duration> {
+    dir.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+}
+
+/// Finds where `program` would be run from. Bare names are searched for in
+/// $PATH, whereas paths are relative to `dir`
+fn find_program(program: &Path, dir: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        let path = dir.join(program);
+        return path.exists().then_some(path);
+    }
+    which(program, &env::var_os("PATH")?)
+}
+
+/// $PATHEXT, or the extensions cmd uses by default if it isn't set
+fn pathext() -> String {
+    env::var("PATHEXT").unwrap_or_else(|_| String::from(".COM;.EXE;.BAT;.CMD"))
+}
+
+/// The extensions in `pathext` (a list like $PATHEXT), in order, lowercased
+/// and without their leading dots
+fn pathext_extensions(pathext: &str) -> impl Iterator<Item = String> + '_ {
+    pathext
+        .split(';')
+        .filter_map(|extension| extension.strip_prefix('.'))
+        .map(str::to_ascii_lowercase)
+}
+
+/// Finds `program` (relative to `dir`) with the first extension in `pathext`
+/// that exists, like cmd does for programs named without an extension
+fn with_pathext(program: &Path, dir: &Path, pathext: &str) -> Option<PathBuf> {
+    pathext_extensions(pathext)
+        .map(|extension| program.with_extension(extension))
+        .find(|program| dir.join(program).is_file())
+}
+
+/// Finds the file `program` would be run from in `path` (a list of
+/// directories, like $PATH). On Windows, names without an extension are also
+/// tried with each extension in $PATHEXT, like cmd does
+fn which(program: &Path, path: &OsStr) -> Option<PathBuf> {
+    let mut candidates = vec![Cow::from(program)];
+    if cfg!(windows) && program.extension().is_none() {
+        candidates.extend(
+            pathext_extensions(&pathext())
+                .map(|extension| program.with_extension(extension).into()),
+        );
+    }
+    env::split_paths(path).find_map(|dir| {
+        candidates
+            .iter()
+            .map(|candidate| dir.join(candidate))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Works out what to run `script` with, if it can't be run directly. On Unix,
+/// executable scripts are run directly, and a shebang is used if there is one.
+/// Otherwise, the interpreter is picked by file extension
+fn interpreter_for(script: &Path) -> Option<Vec<String>> {
+    if cfg!(unix) {
+        if is_executable(script) {
+            return None;
+        }
+        let mut start = [0; 256];
+        let read = fs::File::open(script)
+            .and_then(|mut file| file.read(&mut start))
+            .unwrap_or(0);
+        if let Some(shebang) = start[..read].strip_prefix(b"#!") {
+            let line = shebang.split(|&byte| byte == b'\n').next().unwrap();
+            let interpreter = String::from_utf8_lossy(line)
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            if !interpreter.is_empty() {
+                debug!("using shebang to run {}", script.display());
+                return Some(interpreter);
+            }
+        }
+    }
+    let extension = script.extension()?.to_str()?.to_ascii_lowercase();
+    let interpreter: &[&str] = match extension.as_str() {
+        "sh" => &["sh"],
+        "py" => &["python"],
+        "ps1" => &["powershell", "-ExecutionPolicy", "Bypass", "-File"],
+        _ => return None,
+    };
+    debug!("running {} with {}", script.display(), interpreter[0]);
+    Some(interpreter.iter().map(|&arg| arg.to_owned()).collect())
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().is_ok_and(|metadata| {
+            metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// How often the root is looked for again with `wait_for_files`
+const WAIT_FOR_FILES_INTERVAL: Duration = Duration::from_millis(100);
+
+fn deserialize_optional_duration<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_duration(String::deserialize(deserializer)?).map(Some)
+}
+
+/// Parses a human-friendly duration like "30s" or "1m 30s"
+fn parse_duration<E: Error>(value: String) -> Result<Duration, E> {
+    humantime::parse_duration(&value)
+        .map_err(|why| E::custom(format_args!("invalid duration: {why}")))
+}
+
+/// Creates an error for an unrecognised key in a table, listing the keys that
+/// would've been accepted
+fn unrecognised_key<E: Error>(key: &str, expected: &[&str]) -> E {
+    let (last, rest) = expected
+        .split_last()
+        .expect("there should be at least one expected key");
+    let rest = rest
+        .iter()
+        .map(|key| format!("\"{key}\", "))
+        .collect::<String>();
+    E::custom(format_args!(
+        "unrecognised key \"{key}\", expected {rest}or \"{last}\""
+    ))
+}
+
+/// A dotenv file to load variables from for `run`
+#[derive(Debug)]
+struct Dotenv {
+    /// Relative to the root
+    path: PathBuf,
+    /// Whether the file's variables replace those already in gig's
+    /// environment
+    override_env: bool,
+}
+
+impl Dotenv {
+    /// Sets the variables from the dotenv file in `root` for `command`. Does
+    /// nothing if the file doesn't exist. Unless `override_env` is set,
+    /// variables the command will `inherit` from gig's environment are
+    /// skipped
+    fn apply(
+        &self,
+        command: &mut Command,
+        root: &Path,
+        inherits: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<()> {
+        let path = root.join(&self.path);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {
+                debug!("{} doesn't exist, not loading it", path.display());
+                return Ok(());
+            },
+            Err(why) => {
+                return Err(why).with_context(|| {
+                    format!("couldn't read {}", path.display())
+                })
+            },
+        };
+        let vars = parse_dotenv(&contents)
+            .with_context(|| format!("couldn't parse {}", path.display()))?;
+        for (name, value) in vars {
+            if !self.override_env && inherits(&name) {
+                trace!("{name} is already set, ignoring value from dotenv");
+                continue;
+            }
+            command.env(name, value);
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `NAME=value` lines of a dotenv file. Lines can start with
+/// `export`, and values can be quoted. Double quoted values can use `\n`,
+/// `\"`, and `\\` escapes, whereas single quoted values are taken literally
+fn parse_dotenv(contents: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            bail!("line {} isn't NAME=value", index + 1);
+        };
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let Some(quoted) = quoted.strip_suffix('"') else {
+                bail!("unclosed quote on line {}", index + 1);
+            };
+            let mut unescaped = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some(escaped @ ('"' | '\\')) => unescaped.push(escaped),
+                    // Unknown escapes are kept as they were
+                    Some(other) => {
+                        unescaped.push('\\');
+                        unescaped.push(other);
+                    },
+                    None => unescaped.push('\\'),
+                }
+            }
+            unescaped
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            let Some(quoted) = quoted.strip_suffix('\'') else {
+                bail!("unclosed quote on line {}", index + 1);
+            };
+            quoted.to_owned()
+        } else {
+            // Unquoted values can have trailing comments
+            value
+                .split_once(" #")
+                .map_or(value, |(value, _)| value)
+                .trim_end()
+                .to_owned()
+        };
+        vars.push((name.trim().to_owned(), value));
+    }
+    Ok(vars)
+}
+
+/// Deserializes `exit_code_map`, whose keys are always strings in TOML
+fn deserialize_exit_code_map<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<i32, i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BTreeMap::<String, i32>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(code, mapped)| match code.trim().parse() {
+            Ok(code) => Ok((code, mapped)),
+            Err(_) => Err(D::Error::custom(format!(
+                "invalid exit code {code:?} in exit_code_map"
+            ))),
+        })
+        .collect()
+}
+
+/// Deserializes `load_dotenv`, which is either `true` to load `.env`, the path
+/// to load instead, or a table to also set `override`
+fn deserialize_dotenv<'de, D>(
+    deserializer: D,
+) -> Result<Option<Dotenv>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct DotenvVisitor;
+
+    impl<'de> Visitor<'de> for DotenvVisitor {
+        type Value = Option<Dotenv>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("boolean, path, or load_dotenv table")
+        }
+
+        fn visit_bool<E>(self, load: bool) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(load.then(|| Dotenv {
+                path: PathBuf::from(".env"),
+                override_env: false,
+            }))
+        }
+
+        fn visit_str<E>(self, path: &str) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(Some(Dotenv {
+                path: expand_path(path).map_err(E::custom)?,
+                override_env: false,
+            }))
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut path = PathBuf::from(".env");
+            let mut override_env = false;
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "path" => {
+                        let value = map.next_value::<String>()?;
+                        path = expand_path(&value).map_err(A::Error::custom)?;
+                    },
+                    "override" => override_env = map.next_value()?,
+                    unknown => {
+                        return Err(unrecognised_key(unknown, &[
+                            "path", "override",
+                        ]))
+                    },
+                }
+            }
+            Ok(Some(Dotenv { path, override_env }))
+        }
+    }
+
+    deserializer.deserialize_any(DotenvVisitor)
+}
+
+/// Deserializes either a single table, or an array of them
+fn deserialize_one_or_many<'de, D, T>(
+    deserializer: D,
+) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrManyVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for OneOrManyVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("table or array of tables")
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            T::deserialize(MapAccessDeserializer::new(map)).map(|one| vec![one])
+        }
+
+        fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrManyVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::{
+        borrow::Cow,
+        env,
+        ffi::{OsStr, OsString},
+        fs,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use serde::Deserialize;
+
+    use crate::{
+        apply_profile, deserialize_one_or_many, env_template_with,
+        expand_path_with, expand_vars_with, fill_placeholders, interpreter_for,
+        merge_tables, merged_table, parse_dotenv, pinned_version,
+        remove_from_path, split_command, suggest_config_files, which,
+        with_pathext, AppConfig, BeforeRun, CommandSyntax, FileChecks,
+        FileKind, JsonString, RequiredFile, Resolution, Run, RunConfig,
+        Severity, ToolVersions, NAME, TOOL_VERSIONS_FILE,
+    };
+
+    fn config_with(options: &str) -> AppConfig {
+        toml::from_str(&format!(
+            "{options}\nbefore_run = {{ command = \"true\" }}\nrun = {{ path \
+             = \"x\" }}",
+        ))
+        .expect("should deserialise")
+    }
+
+    #[test]
+    fn lint_config() {
+        // Without a fallback, run.path would have to exist
+        let severities = |options: &str| {
+            config_with(&format!("fallback = {{}}\n{options}"))
+                .lints()
+                .into_iter()
+                .map(|lint| lint.severity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(severities(""), []);
+        assert_eq!(
+            severities("search_parents = true\nroot_offset = \"/abs\""),
+            [Severity::Error, Severity::Warning],
+        );
+        // Every error is reported, not just the first
+        let why = config_with("root_offset = \"/abs\"")
+            .lint()
+            .expect_err("absolute root_offset should fail");
+        assert_eq!(
+            why.to_string(),
+            "2 errors:\n  root_offset must be relative, but is /abs\n  \
+             run.path x doesn't exist, and there's no fallback",
+        );
+    }
+
+    #[test]
+    fn deserialise_example() {
+        let config_file =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        let app_config = AppConfig::load_from_path(&config_file)
+            .expect("should deserialise");
+        dbg!(app_config);
+    }
+
+    #[test]
+    fn glob_required_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let exists = |path: &str, kind: FileKind| {
+            RequiredFile {
+                path: path.into(),
+                kind,
+                content: None,
+                hint: None,
+            }
+            .exists_in(dir, FileChecks::default())
+        };
+        let exists_any_case = |path: &str, kind: FileKind| {
+            RequiredFile {
+                path: path.into(),
+                kind,
+                content: None,
+                hint: None,
+            }
+            .exists_in(dir, FileChecks {
+                case_insensitive: true,
+                readable: false,
+            })
+        };
+        assert!(exists("Cargo.toml", FileKind::Any));
+        assert!(exists("*.toml", FileKind::Any));
+        assert!(exists("src/*.rs", FileKind::File));
+        assert!(exists("s[r]c", FileKind::Dir));
+        assert!(!exists("s[r]c", FileKind::File));
+        assert!(!exists("*.sln", FileKind::Any));
+        assert!(!exists("missing.txt", FileKind::Any));
+        assert!(exists_any_case("CARGO.toml", FileKind::File));
+        assert!(exists_any_case("SRC/Lib.rs", FileKind::File));
+        assert!(exists_any_case("Src/*.RS", FileKind::File));
+        assert!(!exists_any_case("SRC", FileKind::File));
+        if cfg!(target_os = "linux") {
+            assert!(!exists("CARGO.toml", FileKind::Any));
+        }
+    }
+
+    #[test]
+    fn deserialise_required_file_forms() {
+        #[derive(Debug, Deserialize)]
+        struct Wrapper {
+            required_files: Vec<RequiredFile>,
+        }
+
+        let wrapper = toml::from_str::<Wrapper>(
+            r#"required_files = ["a", { path = "b", kind = "dir" }]"#,
+        )
+        .expect("should deserialise");
+        assert!(matches!(wrapper.required_files[0].kind, FileKind::Any));
+        assert!(matches!(wrapper.required_files[1].kind, FileKind::Dir));
+        toml::from_str::<Wrapper>(r#"required_files = [{ kind = "file" }]"#)
+            .expect_err("path should be required");
+    }
+
+    #[test]
+    fn expand_env_vars() {
+        let var = |name: &str| match name {
+            "GIG_TEST_EXPAND" => Ok("value".to_owned()),
+            _ => Err(env::VarError::NotPresent),
+        };
+        let expand = |value: &str| expand_vars_with(value, var).unwrap();
+        assert_eq!(expand("$GIG_TEST_EXPAND/bin"), "value/bin");
+        assert_eq!(expand("${GIG_TEST_EXPAND}bin"), "valuebin");
+        assert_eq!(expand("$$GIG_TEST_EXPAND"), "$GIG_TEST_EXPAND");
+        assert_eq!(expand("cost: $"), "cost: $");
+        assert_eq!(expand("no vars here"), "no vars here");
+        if cfg!(windows) {
+            assert_eq!(expand("%GIG_TEST_EXPAND%\\bin"), "value\\bin");
+            assert_eq!(expand("100%%"), "100%");
+        }
+        expand_vars_with("$GIG_TEST_UNSET", var)
+            .expect_err("unset var should error");
+        expand_vars_with("${GIG_TEST_EXPAND", var).expect_err("unclosed brace");
+
+        let expand_path =
+            |value: &str| expand_path_with(value, var, Some("/home".into()));
+        assert_eq!(expand_path("~/bin").unwrap(), Path::new("/home/bin"));
+        assert_eq!(expand_path("a~b").unwrap(), Path::new("a~b"));
+        expand_path_with("~", var, None).expect_err("no home directory");
+    }
+
+    #[test]
+    fn deserialise_before_run_steps() {
+        #[derive(Debug, Deserialize)]
+        struct Wrapper {
+            #[serde(deserialize_with = "deserialize_one_or_many")]
+            before_run: Vec<BeforeRun>,
+        }
+
+        let single = toml::from_str::<Wrapper>(
+            r#"
+            [before_run]
+            command = "true"
+            "#,
+        )
+        .expect("should deserialise");
+        assert_eq!(single.before_run.len(), 1);
+        let many = toml::from_str::<Wrapper>(
+            r#"
+            [[before_run]]
+            command = "true"
+            [[before_run]]
+            name = "second"
+            command = "false"
+            "#,
+        )
+        .expect("should deserialise");
+        assert_eq!(many.before_run.len(), 2);
+        assert_eq!(many.before_run[1].label(1), "before_run step \"second\"");
+        toml::from_str::<Wrapper>(
+            r#"before_run = { command = "true", script_path = "x.sh" }"#,
+        )
+        .expect_err("command and script_path are mutually exclusive");
+        let capture = toml::from_str::<Wrapper>(
+            r#"before_run = { command = "true", capture_into = "TOKEN" }"#,
+        )
+        .expect("should deserialise");
+        assert_eq!(
+            capture.before_run[0].capture_into.as_deref(),
+            Some("TOKEN")
+        );
+        toml::from_str::<Wrapper>(
+            r#"before_run = { command = "true", capture_into = "A=B" }"#,
+        )
+        .expect_err("capture_into should be a variable name");
+    }
+
+    #[test]
+    fn resolve_root_in_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let cwd = root.join("a/b/c");
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(root.join("marker"), "").unwrap();
+        fs::write(root.join("a/boundary"), "").unwrap();
+
+        let resolve = |options: &str| {
+            config_with(options)
+                .resolve_root_in(&cwd)
+                .ok()
+                .map(|root| root.into_owned())
+        };
+        assert_eq!(resolve(""), Some(cwd.clone()));
+        assert_eq!(resolve(r#"required_files = ["marker"]"#), None);
+        let search = r#"required_files = ["marker"]
+            search_parents = true"#;
+        assert_eq!(resolve(search), Some(root.to_owned()));
+        assert_eq!(resolve(&format!("{search}\nmax_parent_depth = 2")), None);
+        assert_eq!(
+            resolve(&format!("{search}\nsearch_boundary = \"boundary\"")),
+            None,
+        );
+        assert_eq!(
+            config_with(
+                r#"required_files = ["c"]
+                search_children_depth = 2"#,
+            )
+            .resolve_root_in(root)
+            .ok()
+            .map(|root| root.into_owned()),
+            Some(root.join("a/b")),
+        );
+        assert_eq!(
+            resolve(
+                r#"required_files = ["marker", "missing"]
+                required_files_mode = "any"
+                search_parents = true"#,
+            ),
+            Some(root.to_owned()),
+        );
+        // The nearest marker wins, even if a further one is listed first
+        let markers = r#"root_markers = ["marker", "boundary"]"#;
+        assert_eq!(resolve(markers), Some(root.join("a")));
+        assert_eq!(resolve(&format!("{markers}\nmax_parent_depth = 1")), None);
+        assert_eq!(
+            resolve(&format!("{markers}\nrequired_files = [\"boundary\"]")),
+            Some(root.join("a")),
+        );
+        assert_eq!(
+            resolve(&format!("{markers}\nrequired_files = [\"marker\"]")),
+            None,
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn root_cache_follows_case_insensitivity() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let cwd = root.join("a/b");
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(root.join("Marker"), "").unwrap();
+        fs::write(root.join("a/marker"), "").unwrap();
+        // Only meaningful where the filesystem is case sensitive
+        if root.join("MARKER").exists() {
+            return;
+        }
+        // Directories modified in the last couple of seconds aren't cached
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        for dir in [root, &root.join("a"), &cwd] {
+            fs::File::open(dir).unwrap().set_modified(modified).unwrap();
+        }
+
+        let config = |case_insensitive: bool| {
+            config_with(&format!(
+                "required_files = [\"Marker\"]\nsearch_parents = \
+                 true\ncache_root = true\ncase_insensitive_files = \
+                 {case_insensitive}"
+            ))
+        };
+        let resolve = |config: &AppConfig| {
+            config.resolve_root_in(&cwd).unwrap().into_owned()
+        };
+        assert_eq!(resolve(&config(false)), root);
+        assert_eq!(resolve(&config(true)), root.join("a"));
+        for case_insensitive in [false, true] {
+            if let Some((cache_file, _)) =
+                config(case_insensitive).root_cache_file(&cwd)
+            {
+                let _ = fs::remove_file(cache_file);
+            }
+        }
+    }
+
+    #[test]
+    fn required_extensions_in_tree() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("src/pkg")).unwrap();
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        fs::write(root.join("src/pkg/main.py"), "").unwrap();
+        fs::write(root.join("node_modules/index.js"), "").unwrap();
+        fs::write(root.join("README.MD"), "").unwrap();
+
+        let missing = |options: &str| {
+            config_with(options).missing_extensions_in(root).len()
+        };
+        assert_eq!(missing(r#"required_extensions = ["py", ".md"]"#), 1);
+        assert_eq!(
+            missing(
+                r#"required_extensions = ["py", ".md"]
+                case_insensitive_files = true"#,
+            ),
+            0,
+        );
+        assert_eq!(
+            missing(
+                r#"required_extensions = ["py"]
+                required_extensions_depth = 1"#,
+            ),
+            1,
+        );
+        assert_eq!(
+            missing(
+                r#"required_extensions = ["py"]
+                required_extensions_ignore = ["src"]"#,
+            ),
+            1,
+        );
+        assert_eq!(missing(r#"required_extensions = ["js"]"#), 1);
+    }
+
+    #[test]
+    fn find_config_in_dirs() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let config_file = second.path().join(format!("{}.toml", &*NAME));
+        fs::write(&config_file, "").unwrap();
+
+        let found = AppConfig::find_in(&[first.path(), second.path()])
+            .expect("should find config");
+        assert_eq!(found, config_file);
+        AppConfig::find_in(&[first.path()]).expect_err("no config in dir");
+
+        let typo = format!("{}x", &*NAME);
+        fs::write(first.path().join(format!("{typo}.toml")), "").unwrap();
+        fs::write(first.path().join("notes.txt"), "").unwrap();
+        let why = AppConfig::find_in(&[first.path()])
+            .expect_err("config is for a different name")
+            .to_string();
+        assert!(why.contains(&format!("GIG_OVERRIDE={typo}")), "{why}");
+
+        // Unrelated config files aren't suggested
+        fs::write(second.path().join("wrnech.toml"), "").unwrap();
+        fs::write(second.path().join("Cargo.toml"), "").unwrap();
+        let suggestions = suggest_config_files(&[second.path()], "wrench")
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(suggestions, ["wrnech"]);
+    }
+
+    #[test]
+    fn merge_found_configs() {
+        let project = tempfile::tempdir().unwrap();
+        let team = tempfile::tempdir().unwrap();
+        let empty = tempfile::tempdir().unwrap();
+        fs::write(
+            team.path().join(format!("{}.toml", &*NAME)),
+            "search_parents = true\nbefore_run = []\nrun = { path = \"base\", \
+             timeout = \"1m\" }",
+        )
+        .unwrap();
+        fs::write(
+            project.path().join(format!("{}.toml", &*NAME)),
+            "run = { path = \"bin/tool\" }",
+        )
+        .unwrap();
+
+        let config_files = AppConfig::find_all_in(&[
+            project.path(),
+            empty.path(),
+            team.path(),
+        ]);
+        assert_eq!(config_files.len(), 2);
+        let config = AppConfig::load_from_paths(&config_files)
+            .expect("merged config should load");
+        assert!(config.search_parents);
+        assert!(config.run.timeout.is_some());
+        let Run::Executable(path) = &config.run.target else {
+            panic!("should be an executable path");
+        };
+        assert_eq!(path, Path::new("bin/tool"));
+        assert_eq!(config.path, config_files[0]);
+
+        // What GIG_DUMP_CONFIG prints can be loaded again as one config
+        let dumped =
+            toml::to_string(&merged_table(&config_files).unwrap()).unwrap();
+        let dumped_path = empty.path().join(format!("{}.toml", &*NAME));
+        fs::write(&dumped_path, dumped).unwrap();
+        let reloaded = AppConfig::load_from_path(&dumped_path)
+            .expect("dumped config should load");
+        assert!(reloaded.search_parents);
+        assert!(reloaded.run.timeout.is_some());
+
+        AppConfig::load_from_paths(&[]).expect_err("there's nothing to load");
+    }
+
+    #[test]
+    fn locate_config_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join(format!("{}.toml", &*NAME));
+        fs::write(&config_file, "before_run = []\n[run]\npath = 1\n").unwrap();
+        let why = format!(
+            "{:#}",
+            AppConfig::load_from_path(&config_file)
+                .expect_err("path should be a string"),
+        );
+        assert!(why.contains("line 3, column 8"), "{why}");
+        // Errors only in the merged config can't be located
+        fs::write(
+            &config_file,
+            format!(
+                "before_run = []\n[run]\npath = \"x\"\n[{}]\nrun = 1\n",
+                env::consts::OS,
+            ),
+        )
+        .unwrap();
+        let why = format!(
+            "{:#}",
+            AppConfig::load_from_path(&config_file).unwrap_err(),
+        );
+        assert!(why.starts_with("invalid config "), "{why}");
+    }
+
+    #[test]
+    fn merge_platform_overrides() {
+        let mut base = toml::from_str::<toml::Table>(
+            r#"
+            required_files = ["a", "b"]
+            [run]
+            path = "bin/tool"
+            timeout = "1m"
+            "#,
+        )
+        .unwrap();
+        let overrides = toml::from_str::<toml::Table>(
+            r#"
+            required_files = ["c"]
+            [run]
+            path = "bin\\tool.exe"
+            "#,
+        )
+        .unwrap();
+        merge_tables(&mut base, overrides);
+        let expected = toml::from_str::<toml::Table>(
+            r#"
+            required_files = ["c"]
+            [run]
+            path = "bin\\tool.exe"
+            timeout = "1m"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+
+        // Giving a different target replaces the base's
+        let overrides =
+            toml::from_str::<toml::Table>("run = { subcommand_of = \"tool\" }")
+                .unwrap();
+        merge_tables(&mut base, overrides);
+        let expected = toml::from_str::<toml::Table>(
+            r#"
+            required_files = ["c"]
+            [run]
+            subcommand_of = "tool"
+            timeout = "1m"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn select_profile() {
+        let config = toml::from_str::<toml::Table>(
+            r#"
+            search_parents = true
+            [run]
+            path = "bin/tool"
+            [profiles.ci.run]
+            timeout = "10m"
+            [profiles.dev]
+            search_parents = false
+            "#,
+        )
+        .unwrap();
+
+        let mut base = config.clone();
+        apply_profile(&mut base, None).unwrap();
+        let expected = toml::from_str::<toml::Table>(
+            "search_parents = true\nrun = { path = \"bin/tool\" }",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+
+        let mut ci = config.clone();
+        apply_profile(&mut ci, Some("ci")).unwrap();
+        let expected = toml::from_str::<toml::Table>(
+            "search_parents = true\nrun = { path = \"bin/tool\", timeout = \
+             \"10m\" }",
+        )
+        .unwrap();
+        assert_eq!(ci, expected);
+
+        let why = apply_profile(&mut config.clone(), Some("prod"))
+            .expect_err("prod isn't a profile")
+            .to_string();
+        assert!(why.contains("ci, dev"), "{why}");
+    }
+
+    #[test]
+    fn deserialise_subcommand_of_forms() {
+        let subcommand_of = |run: &str| {
+            let Run::SubcommandOf(this) =
+                toml::from_str::<RunConfig>(run).unwrap().target
+            else {
+                panic!("should be subcommand_of");
+            };
+            (this.program, this.subcommand)
+        };
+        assert_eq!(
+            subcommand_of(r#"subcommand_of = "cargo""#),
+            ("cargo".to_owned(), None)
+        );
+        assert_eq!(
+            subcommand_of(
+                r#"subcommand_of = { program = "cargo", subcommand = "nextest" }"#,
+            ),
+            ("cargo".to_owned(), Some("nextest".to_owned())),
+        );
+        toml::from_str::<RunConfig>(r#"subcommand_of = { subcommand = "x" }"#)
+            .expect_err("program should be required");
+    }
+
+    #[test]
+    fn deserialise_run_path_forms() {
+        let target =
+            |run: &str| toml::from_str::<RunConfig>(run).unwrap().target;
+        assert!(matches!(target(r#"path = "bin/""#), Run::PrependFolder(_)));
+        assert!(matches!(target(r#"path = "bin/tool""#), Run::Executable(_)));
+        let Run::PrependFolder(folder) = target(r#"path = { folder = "bin" }"#)
+        else {
+            panic!("should be a folder");
+        };
+        assert_eq!(folder, Path::new("bin"));
+        assert!(matches!(
+            target(r#"path = { exe = "bin/tool" }"#),
+            Run::Executable(_),
+        ));
+        toml::from_str::<RunConfig>(r#"path = { folder = "a", exe = "b" }"#)
+            .expect_err("folder and exe are mutually exclusive");
+        toml::from_str::<RunConfig>(r#"path = { dir = "bin" }"#)
+            .expect_err("dir isn't a key");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn dispatch_to_missing_program_falls_back() {
+        use crate::{launch, Stage};
+
+        let config = toml::from_str::<AppConfig>(
+            r#"before_run = []
+            run = { dispatch = { build = "bin/not-a-real-program-for-gig" } }
+            fallback = { command = "true" }"#,
+        )
+        .unwrap();
+        let (status, stage) =
+            launch(&config, [OsString::from("build")].into_iter()).unwrap();
+        assert!(status.success());
+        assert!(matches!(stage, Stage::Fallback));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn failed_before_run_status() {
+        use crate::{launch, Stage};
+
+        let config = toml::from_str::<AppConfig>(
+            r#"before_run = { command = "sh -c 'exit 3'" }
+            run = { path = "/bin/sh" }"#,
+        )
+        .unwrap();
+        let (status, stage) = launch(&config, std::iter::empty()).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(stage, Stage::BeforeRun);
+    }
+
+    #[test]
+    fn dispatch_on_first_arg() {
+        let run = toml::from_str::<RunConfig>(
+            r#"dispatch = { build = "bin/build", test = { shell = "make test" } }"#,
+        )
+        .unwrap();
+        let args = [OsString::from("build"), OsString::from("--release")];
+        let (target, rest) = run.target.dispatch(&args).unwrap();
+        assert!(matches!(target, Run::Executable(_)));
+        assert_eq!(rest, &args[1..]);
+        run.target
+            .dispatch(&[OsString::from("lint")])
+            .expect_err("there's no default");
+
+        let run = toml::from_str::<RunConfig>(
+            r#"dispatch = { build = "bin/build", default = "bin/tool" }"#,
+        )
+        .unwrap();
+        let (target, rest) = run.target.dispatch(&args[1..]).unwrap();
+        let Run::Executable(path) = target else {
+            panic!("should be the default");
+        };
+        assert_eq!(path, Path::new("bin/tool"));
+        assert_eq!(rest, &args[1..]);
+        toml::from_str::<RunConfig>(r#"dispatch = { a = { dispatch = {} } }"#)
+            .expect_err("dispatch targets can't dispatch");
+    }
+
+    #[test]
+    fn tool_versions_pins() {
+        let contents = "# pinned for CI\nnodejs 20.11.0 18.19.0\npython \
+                        system\nruby 3.3.0 # latest\n";
+        assert_eq!(pinned_version(contents, "nodejs"), Some("20.11.0"));
+        assert_eq!(pinned_version(contents, "ruby"), Some("3.3.0"));
+        assert_eq!(pinned_version(contents, "python"), None);
+        assert_eq!(pinned_version(contents, "node"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool_versions = toml::from_str::<ToolVersions>(
+            r#"tool = "nodejs"
+path = "/opt/nodejs/{version}/bin/node""#,
+        )
+        .unwrap();
+        assert_eq!(tool_versions.path_in(dir.path()).unwrap(), None);
+        fs::write(dir.path().join(TOOL_VERSIONS_FILE), contents).unwrap();
+        assert_eq!(
+            tool_versions.path_in(dir.path()).unwrap(),
+            Some(PathBuf::from("/opt/nodejs/20.11.0/bin/node")),
+        );
+        toml::from_str::<ToolVersions>(
+            r#"tool = "nodejs"
+path = "/opt/nodejs/bin/node""#,
+        )
+        .expect_err("path needs {version}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", feature = "yaml"))]
+    fn load_other_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = dir.path().join(format!("{}.yaml", &*NAME));
+        fs::write(
+            &yaml,
+            "required_files: [a]\nbefore_run:\n  command: \"true\"\nrun:\n  \
+             path: bin/x\n",
+        )
+        .unwrap();
+        assert_eq!(AppConfig::find_in(&[dir.path()]).unwrap(), yaml);
+        AppConfig::load_from_path(&yaml).expect("yaml should load");
+
+        let json = dir.path().join(format!("{}.json", &*NAME));
+        fs::write(
+            &json,
+            r#"{"before_run": {"command": "true"}, "run": {"path": "bin/x"}}"#,
+        )
+        .unwrap();
+        AppConfig::load_from_path(&json).expect("json should load");
+
+        let toml = dir.path().join(format!("{}.toml", &*NAME));
+        fs::write(&toml, "").unwrap();
+        assert_eq!(AppConfig::find_in(&[dir.path()]).unwrap(), toml);
+    }
+
+    #[test]
+    fn required_file_content() {
+        #[derive(Debug, Deserialize)]
+        struct Wrapper {
+            required_files: Vec<RequiredFile>,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("package.json"), r#"{"workspaces": []}"#)
+            .unwrap();
+        let wrapper = toml::from_str::<Wrapper>(
+            r#"required_files = [
+                { path = "package.json", contains = "workspaces" },
+                { path = "*.json", matches = '"work\w+"' },
+                { path = "package.json", contains = "private" },
+            ]"#,
+        )
+        .expect("should deserialise");
+        let exists = wrapper
+            .required_files
+            .iter()
+            .map(|file| file.exists_in(dir.path(), FileChecks::default()))
+            .collect::<Vec<_>>();
+        assert_eq!(exists, [true, true, false]);
+        toml::from_str::<Wrapper>(
+            r#"required_files = [{ path = "a", kind = "dir", contains = "b" }]"#,
+        )
+        .expect_err("directories have no content");
+    }
+
+    #[test]
+    fn parse_dotenv_lines() {
+        let vars = parse_dotenv(
+            r#"
+            # comment
+            PLAIN=value # trailing comment
+            export EXPORTED = spaced
+            DOUBLE="line\nbreak \"quoted\""
+            SINGLE='$not\nescaped'
+            EMPTY=
+            "#,
+        )
+        .expect("should parse");
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(vars, [
+            ("PLAIN", "value"),
+            ("EXPORTED", "spaced"),
+            ("DOUBLE", "line\nbreak \"quoted\""),
+            ("SINGLE", "$not\\nescaped"),
+            ("EMPTY", ""),
+        ]);
+        parse_dotenv("NO_EQUALS").expect_err("should need =");
+        parse_dotenv("UNCLOSED=\"value")
+            .expect_err("should need closing quote");
+    }
+
+    #[test]
+    fn args_template_placement() {
+        let config = toml::from_str::<AppConfig>(
+            r#"
+            before_run = { command = "true" }
+            [run]
+            path = "docker"
+            args_prepend = ["run"]
+            args_template = ["--rm", "image", "{args}", "--end"]
+            "#,
+        )
+        .expect("should deserialise");
+        let command = config
+            .generate_run(Path::new("."), &["a".into(), "b".into()])
+            .unwrap();
+        let args = command.command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, ["run", "--rm", "image", "a", "b", "--end"]);
+        toml::from_str::<AppConfig>(
+            r#"
+            before_run = { command = "true" }
+            run = { path = "docker", args_template = ["run"] }
+            "#,
+        )
+        .expect_err("template needs a placeholder");
+    }
+
+    #[test]
+    fn pathext_resolution() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("bin")).unwrap();
+        for stub in ["tool.cmd", "tool.bat", "other.exe"] {
+            fs::write(dir.path().join("bin").join(stub), "").unwrap();
+        }
+        let resolve = |program: &str, pathext: &str| {
+            with_pathext(Path::new(program), dir.path(), pathext)
+        };
+        let tool = Path::new("bin").join("tool");
+        assert_eq!(
+            resolve("bin/tool", ".COM;.BAT;.CMD"),
+            Some(tool.with_extension("bat")),
+        );
+        assert_eq!(
+            resolve("bin/tool", ".EXE;.CMD;.BAT"),
+            Some(tool.with_extension("cmd")),
+        );
+        assert_eq!(resolve("bin/tool", ".EXE"), None);
+        assert_eq!(resolve("bin/missing", ".COM;.BAT;.CMD"), None);
+    }
+
+    #[test]
+    fn args_filter() {
+        let run_args = |filter: &str| {
+            let config = toml::from_str::<AppConfig>(&format!(
+                "before_run = []\nrun = {{ path = \"tool\", args_filter = \
+                 {filter} }}",
+            ))
+            .expect("should deserialise");
+            let args = ["--gig-x", "a", "--gig-y=1", "b"].map(OsString::from);
+            let command = config.generate_run(Path::new("."), &args).unwrap();
+            command
+                .command
+                .get_args()
+                .map(|arg| arg.to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run_args(r#"["--gig-x"]"#), ["a", "--gig-y=1", "b"]);
+        assert_eq!(run_args(r#"{ matches = "^--gig-" }"#), ["a", "b"]);
+        assert_eq!(run_args(r#"{ drop = ["a"], matches = "=" }"#), [
+            "--gig-x", "b"
+        ],);
+    }
+
+    #[test]
+    fn script_interpreters() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            interpreter_for(&path)
+        };
+        assert_eq!(script("setup.sh", "echo hi"), Some(vec!["sh".to_owned()]));
+        assert_eq!(script("setup.PY", ""), Some(vec!["python".to_owned()]));
+        assert_eq!(script("setup", ""), None);
+        if cfg!(unix) {
+            assert_eq!(
+                script("setup.py", "#!/usr/bin/env python3\nprint()"),
+                Some(vec!["/usr/bin/env".to_owned(), "python3".to_owned()]),
+            );
+        }
+    }
+
+    #[test]
+    fn extends_base_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("common.toml"),
+            "search_parents = true\nbefore_run = { command = \"true\" }\nrun \
+             = { path = \"base\", timeout = \"1m\" }",
+        )
+        .unwrap();
+        let config_file = dir.path().join("tool.toml");
+        fs::write(
+            &config_file,
+            "extends = \"common.toml\"\nrun = { path = \"bin/tool\" }",
+        )
+        .unwrap();
+        let config =
+            AppConfig::load_from_path(&config_file).expect("should load");
+        assert!(config.search_parents);
+        assert!(config.run.timeout.is_some());
+        let Run::Executable(path) = &config.run.target else {
+            panic!("should be an executable path");
+        };
+        assert_eq!(path, Path::new("bin/tool"));
+
+        fs::write(dir.path().join("common.toml"), "extends = \"tool.toml\"")
+            .unwrap();
+        AppConfig::load_from_path(&config_file).expect_err("should be a cycle");
+    }
+
+    #[test]
+    fn remove_gig_from_path() {
+        let (path, gig_dir, expected) = if cfg!(windows) {
+            (
+                r"C:\Windows;c:\tools\GIG;D:\bin",
+                r"C:\Tools\gig",
+                r"C:\Windows;D:\bin",
+            )
+        } else {
+            ("/usr/bin:/opt/gig:/bin", "/opt/gig", "/usr/bin:/bin")
+        };
+        assert_eq!(
+            remove_from_path(OsStr::new(path), Path::new(gig_dir)),
+            OsStr::new(expected),
+        );
+    }
+
+    #[test]
+    fn before_run_staleness() {
+        let dir = tempfile::tempdir().unwrap();
+        let before_run = toml::from_str::<BeforeRun>(
+            r#"
+            command = "generate"
+            when_newer = ["schema.json"]
+            than = ["generated.rs"]
+            "#,
+        )
+        .expect("should deserialise");
+        let touch = |name: &str, secs_ago: u64| {
+            let file = fs::File::create(dir.path().join(name)).unwrap();
+            file.set_modified(
+                SystemTime::now() - Duration::from_secs(secs_ago),
+            )
+            .unwrap();
+        };
+        touch("schema.json", 60);
+        assert!(!before_run.is_up_to_date(dir.path()), "output is missing");
+        touch("generated.rs", 30);
+        assert!(before_run.is_up_to_date(dir.path()));
+        touch("schema.json", 0);
+        assert!(!before_run.is_up_to_date(dir.path()), "input is newer");
+        toml::from_str::<BeforeRun>(
+            r#"command = "generate"
+            than = ["generated.rs"]"#,
+        )
+        .expect_err("when_newer is needed too");
+    }
+
+    #[test]
+    fn split_command_syntaxes() {
+        let posix = split_command(r#"echo "a b" 'c d'"#, CommandSyntax::Posix);
+        assert_eq!(posix, ["echo", "a b", "c d"]);
+        let cmd = split_command(
+            r#"tool "C:\Program Files\\" a\b \"q\" "x""y""#,
+            CommandSyntax::Cmd,
+        );
+        assert_eq!(cmd, [
+            "tool",
+            r"C:\Program Files\",
+            r"a\b",
+            r#""q""#,
+            r#"x"y"#
+        ]);
+        let powershell = split_command(
+            r#"Write-Host 'it''s' "a`"b" c`` d"#,
+            CommandSyntax::Powershell,
+        );
+        assert_eq!(powershell, ["Write-Host", "it's", r#"a"b"#, "c`", "d"]);
+        let none = split_command("My Tool.exe", CommandSyntax::None);
+        assert_eq!(none, ["My Tool.exe"]);
+        assert!(split_command("  ", CommandSyntax::Cmd).is_empty());
+    }
+
+    #[test]
+    fn exit_code_map() {
+        let config = config_with(r#"exit_code_map = { "1" = 0, "-2" = 1 }"#);
+        assert_eq!(config.map_exit_code(1), 0);
+        assert_eq!(config.map_exit_code(-2), 1);
+        assert_eq!(config.map_exit_code(3), 3);
+        toml::from_str::<AppConfig>(
+            r#"exit_code_map = { one = 0 }
+            before_run = { command = "true" }
+            run = { path = "x" }"#,
+        )
+        .expect_err("exit code keys should be numbers");
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let delays = |retry_delay: &str| {
+            let config = toml::from_str::<AppConfig>(&format!(
+                "before_run = []\nrun = {{ path = \"x\", retries = 9, \
+                 retry_delay = \"{retry_delay}\" }}",
+            ))
+            .unwrap();
+            [1, 2, 3, 8, u32::MAX].map(|retry| config.run.retry_delay(retry))
+        };
+        let secs = Duration::from_secs;
+        assert_eq!(delays("5s"), [5, 10, 20, 60, 60].map(secs));
+        assert_eq!(delays("2m"), [120; 5].map(secs));
+        assert_eq!(delays("0s"), [0; 5].map(secs));
+    }
+
+    #[test]
+    fn json_string_escaping() {
+        assert_eq!(JsonString("plain").to_string(), r#""plain""#);
+        assert_eq!(
+            JsonString("say \"hi\"\n\tC:\\").to_string(),
+            r#""say \"hi\"\n\tC:\\""#,
+        );
+        assert_eq!(JsonString("\u{1b}[0m").to_string(), r#""\u001b[0m""#);
+    }
+
+    #[test]
+    fn root_offset() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("config")).unwrap();
+        fs::write(root.join("config/app.toml"), "").unwrap();
+
+        let resolve = |offset| {
+            config_with(&format!(
+                r#"required_files = ["app.toml"]
+                root_offset = "{offset}""#,
+            ))
+            .resolve_root_in(&root.join("config"))
+            .map(Cow::into_owned)
+        };
+        let resolve_err = |offset| resolve(offset).unwrap_err().to_string();
+        assert_eq!(resolve("..").unwrap(), root);
+        assert_eq!(resolve("../config/./").unwrap(), root.join("config"));
+        assert!(resolve_err("app.toml").contains("isn't a directory"));
+        assert!(resolve_err("missing").contains("isn't a directory"));
+        let above = "../".repeat(root.components().count() + 1);
+        assert!(resolve_err(&above).contains("goes above"));
+        assert!(config_with(r#"root_offset = "/abs""#).lint().is_err());
+        // Unlinted configs get an error instead
+        config_with(r#"root_offset = "/abs""#)
+            .resolve_root_in(root)
+            .expect_err("root_offset can't be absolute");
+        #[cfg(windows)]
+        assert!(config_with(r#"root_offset = "C:foo""#).lint().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shell_passes_args_safely() {
+        let output = crate::shell_command("printf '%s\\n' first", &[
+            OsStr::new("a b"),
+            OsStr::new("$(echo unsafe); exit 1"),
+        ])
+        .output()
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "first\na b\n$(echo unsafe); exit 1\n",
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn cmd_escaping() {
+        assert_eq!(crate::cmd_escape("plain"), r#"^"plain^""#);
+        assert_eq!(
+            crate::cmd_escape(r#"a "b" & c\"#),
+            r#"^"a \^"b\^" ^& c\\^""#
+        );
+    }
+
+    #[test]
+    fn fallback_candidates() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        fs::write(second.path().join("python"), "").unwrap();
+        let path = env::join_paths([first.path(), second.path()]).unwrap();
+        assert_eq!(
+            which(Path::new("python"), &path),
+            Some(second.path().join("python")),
+        );
+        assert_eq!(which(Path::new("python3"), &path), None);
+
+        let config = config_with(
+            r#"fallback = { candidates = ["not-a-real-program-for-gig"] }"#,
+        );
+        let fallbacks = config.generate_fallback(&[]);
+        let [Err(why)] = fallbacks.as_slice() else {
+            panic!("fallback shouldn't be found");
+        };
+        assert!(why.to_string().contains("not-a-real-program-for-gig"));
+    }
+
+    #[test]
+    fn required_files_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("markers.txt"),
+            "# project markers\npackage.json\n\n  src/*.ts  \n",
+        )
+        .unwrap();
+        let config_file = dir.path().join("tool.toml");
+        fs::write(
+            &config_file,
+            r#"required_files = { from = "markers.txt" }
+            before_run = { command = "true" }
+            run = { path = "bin/x" }"#,
+        )
+        .unwrap();
+        let config = AppConfig::load_from_path(&config_file).unwrap();
+        let paths = config
+            .required_files
+            .iter()
+            .map(|file| file.path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["package.json", "src/*.ts"]);
+    }
+
+    #[test]
+    fn paths_relative_to_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("tool.toml");
+        let write_config = |relative_to| {
+            fs::write(
+                &config_file,
+                format!(
+                    r#"paths_relative_to = "{relative_to}"
+                    before_run = {{ command = "true" }}
+                    run = {{ path = "bin/x", working_directory = "sub" }}"#,
+                ),
+            )
+            .unwrap();
+            AppConfig::load_from_path(&config_file).unwrap()
+        };
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("sub")).unwrap();
+        let program = |config: AppConfig| {
+            let command = config.generate_run(root.path(), &[]).unwrap();
+            PathBuf::from(command.command.get_program())
+        };
+        assert_eq!(program(write_config("root")), root.path().join("bin/x"));
+        assert_eq!(program(write_config("config")), dir.path().join("bin/x"));
+    }
+
+    #[test]
+    fn script_path_relative_to_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("tool.toml");
+        fs::write(dir.path().join("setup.sh"), "#!/bin/sh\n").unwrap();
+        fs::write(
+            &config_file,
+            r#"paths_relative_to = "config"
+            before_run = { script_path = "setup.sh" }
+            run = { path = "bin/x" }"#,
+        )
+        .unwrap();
+        // Loading lints the config, which would fail if the script was looked
+        // for in the CWD
+        let config = AppConfig::load_from_path(&config_file).unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let command = config
+            .generate_before_run(&config.before_run[0], root.path())
+            .unwrap();
+        let args = command.command.get_args().collect::<Vec<_>>();
+        assert_eq!(args, [dir.path().join("setup.sh").as_os_str()]);
+
+        fs::remove_file(dir.path().join("setup.sh")).unwrap();
+        AppConfig::load_from_path(&config_file)
+            .expect_err("the script doesn't exist");
+    }
+
+    #[test]
+    fn unresolvable_programs() {
+        let root = tempfile::tempdir().unwrap();
+        let resolution = |before_run: &str| {
+            let config = toml::from_str::<AppConfig>(&format!(
+                "before_run = {before_run}\nrun = {{ path = \"bin/x\" }}"
+            ))
+            .unwrap();
+            let command = config.generate_run(root.path(), &[]).unwrap();
+            config.run_resolution(&config.run.target, &command.command)
+        };
+        assert_eq!(resolution("[]"), Resolution::NotFound);
+        // before_run could be what builds it
+        assert_eq!(
+            resolution(r#"{ command = "make" }"#),
+            Resolution::NotBuiltYet,
+        );
+        let program = root.path().join("bin/x");
+        fs::create_dir(root.path().join("bin")).unwrap();
+        fs::write(&program, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                resolution("[]"),
+                Resolution::NotExecutable(program.clone()),
+            );
+            fs::set_permissions(&program, fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+        assert_eq!(resolution("[]"), Resolution::Found);
+    }
+
+    #[test]
+    fn captured_env_is_redacted() {
+        let mut command =
+            config_with("").generate_run(Path::new("."), &[]).unwrap();
+        command.set_captured_env("TOKEN", "hunter2");
+        let displayed = command.to_string();
+        assert!(displayed.contains("TOKEN=<redacted>"));
+        assert!(!displayed.contains("hunter2"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn display_non_utf8_program() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join(OsStr::from_bytes(b"proj\xff"));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let config = toml::from_str::<AppConfig>(
+            "before_run = []\nrun = { path = \"bin/tool\", working_directory \
+             = \"sub\" }",
+        )
+        .unwrap();
+        let command = config.generate_run(&root, &[]).unwrap();
+        assert!(command.to_string().contains("proj\u{FFFD}/bin/tool"));
+    }
+
+    #[test]
+    fn env_placeholders() {
+        let var = |name: &str| match name {
+            "GIG_TEST_PLACEHOLDER" => Ok("{root}".to_owned()),
+            _ => Err(env::VarError::NotPresent),
+        };
+        let fill = |value: &str, root: Option<&str>| {
+            let template = env_template_with(value, var)?;
+            fill_placeholders(&template, root.map(Path::new))
+        };
+        assert_eq!(
+            fill("{root}/config", Some("/project")).unwrap(),
+            "/project/config",
+        );
+        assert_eq!(fill("{name}", None).unwrap(), NAME.as_ref());
+        assert_eq!(
+            fill("{{root}} ${GIG_TEST_PLACEHOLDER}", None).unwrap(),
+            "{root} {root}",
+        );
+        assert_eq!(
+            fill(r#"{"json": true}"#, None).unwrap(),
+            r#"{"json": true}"#
+        );
+        fill("{home}", None).expect_err("unknown placeholder");
+        fill("{root}", None).expect_err("no root for fallbacks");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn background_child_keeps_pipe_open() {
+        use std::{process::Command, sync::atomic::AtomicUsize, time::Instant};
+
+        use crate::{InputMode, LoggedCommand, OutputMode};
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 3 & echo done"]);
+        let command = LoggedCommand {
+            command,
+            timeout: None,
+            stdin: InputMode::Null,
+            stdout: OutputMode::Capture,
+            stderr: OutputMode::Inherit,
+            resolution: Resolution::Unchecked,
+            redacted_env: Vec::new(),
+        };
+        let start = Instant::now();
+        let output = command.output(&AtomicUsize::new(0)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(output.stdout.as_deref(), Some("done\n"));
+    }
+}
@@ -1,506 +1,230 @@
-#![deny(clippy::undocumented_unsafe_blocks)]
-#![deny(unsafe_op_in_unsafe_fn)]
-
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
 use std::{
-    borrow::Cow,
-    env,
-    ffi::OsStr,
-    fmt, fs,
-    io::Write,
-    ops::ControlFlow,
-    path::{Path, PathBuf},
-    process::{Command, ExitCode, ExitStatus},
+    env, fs, io::Write, path::Path, process::ExitCode, time::SystemTime,
 };
 
-use anyhow::{anyhow, bail, Context};
-use env_logger::{fmt::Color, Env};
-use log::{debug, error, info, trace, warn, Level, LevelFilter};
-use once_cell::sync::Lazy;
-use serde::{
-    de::{Error, MapAccess, Visitor},
-    Deserialize, Deserializer,
+use env_logger::{fmt::Color, Env, Logger, Target, WriteStyle};
+use get_it_going::{
+    check, dump_config, info, list_configs, AppConfig, JsonString, Stage,
+    COLOR, DRY_RUN, NAME, TIMING,
+};
+use log::{
+    debug, error, trace, warn, Level, LevelFilter, Log, Metadata, Record,
 };
-use shlex::Shlex;
 
-#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
-compile_error!("unsupported OS: only Windows, MacOS, and Linux currently");
+fn main() -> ExitCode {
+    init_logging();
 
-static NAME: Lazy<Box<str>> = Lazy::new(|| match env::var("GIG_OVERRIDE") {
-    Ok(name) => name.into_boxed_str(),
-    Err(_) => {
-        let executable = env::current_exe().expect("can't access own path");
-        executable.file_stem().unwrap().to_string_lossy().into()
-    },
-});
+    if env::var_os("GIG_VERSION").is_some() {
+        println!("get-it-going v{}", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
 
-static SYSTEM_WIDE_CONFIG_DIRECTORY: Lazy<&Path> = Lazy::new(|| {
-    if cfg!(windows) {
-        Path::new("C:\\Program Files\\Common Files\\get-it-going")
-    } else if cfg!(target_os = "macos") {
-        Path::new("/Library/Application Support/get-it-going")
-    } else if cfg!(target_os = "linux") {
-        Path::new("/etc/get-it-going")
-    } else {
-        unreachable!("compile_error! for unsupported OSes should prevent this")
+    if env::var_os("GIG_INFO").is_some() {
+        info();
+        return ExitCode::SUCCESS;
     }
-});
 
-static CWD: Lazy<PathBuf> = Lazy::new(|| {
-    env::current_dir()
-        .expect("get-it-going must have access to current working directory")
-});
+    if env::var_os("GIG_LIST_CONFIGS").is_some() {
+        list_configs();
+        return ExitCode::SUCCESS;
+    }
 
-fn main() -> ExitCode {
-    env_logger::builder()
-        .filter_level(LevelFilter::Warn)
-        .parse_env(Env::new().filter("GIG_LOG"))
-        .format(move |buf, record| {
-            let mut style = buf.style();
-            match record.level() {
-                Level::Error => {
-                    style.set_color(Color::Red);
-                },
-                Level::Warn => {
-                    style.set_color(Color::Yellow);
-                },
-                Level::Info => {},
-                Level::Debug | Level::Trace => {
-                    style.set_dimmed(true);
-                },
-            }
-            writeln!(
-                buf,
-                "[{} {}]: {}",
-                NAME.as_ref(),
-                style.value(record.level()),
-                record.args()
-            )
-        })
-        .init();
+    if env::var_os("GIG_DUMP_CONFIG").is_some() {
+        return match dump_config() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(why) => {
+                error!("unable to dump config: {why:#}");
+                ExitCode::FAILURE
+            },
+        };
+    }
 
-    if env::var_os("GIG_VERSION").is_some() {
-        println!("get-it-going v{}", env!("CARGO_PKG_VERSION"));
-        return ExitCode::SUCCESS;
+    if env::var_os("GIG_CHECK").is_some() {
+        return match check() {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(why) => {
+                error!("config check failed: {why:#}");
+                ExitCode::FAILURE
+            },
+        };
     }
 
-    match _main() {
-        Ok(status) => {
+    let config = match AppConfig::find_and_load() {
+        Ok(config) => config,
+        Err(why) => {
+            error!("unable to launch {}: {why:#}", NAME.as_ref());
+            return ExitCode::FAILURE;
+        },
+    };
+    match get_it_going::launch(&config, env::args_os().skip(1)) {
+        Ok((status, stage)) => {
             // Some scuff to get i32 exit codes into u8 without wrapping to
             // non-zero to zero
-            let orig_code = status.code();
+            // exit_code_map is for the program's exit codes, not before_run's
+            let orig_code = status.code().map(|code| match stage {
+                Stage::BeforeRun => code,
+                Stage::Run | Stage::Fallback => config.map_exit_code(code),
+            });
+            // Like shells do, report being killed by a signal as 128 + the
+            // signal number
+            #[cfg(unix)]
+            if let Some(signal) = status.signal() {
+                let exit_code = 128u8.wrapping_add(signal as u8);
+                debug!(
+                    "{stage} killed by signal {signal}, exiting with \
+                     {exit_code}"
+                );
+                return ExitCode::from(exit_code);
+            }
             let exit_code: u8 = orig_code
                 .map_or(!status.success() as _, |orig_exit_code| {
                     orig_exit_code.unsigned_abs() as _
                 });
             // Only print conversion before/after if the value changed or was
             // made up
-            if orig_code.map_or(true, |orig_exit_code| {
-                exit_code as i32 != orig_exit_code
-            }) {
+            if orig_code != Some(exit_code as i32) {
                 debug!(
-                    "exited with status {orig_code:?}, converted to \
+                    "{stage} exited with status {orig_code:?}, converted to \
                      {exit_code}",
                 );
             } else {
-                trace!("exit code {exit_code} converted cleanly (no loss)");
+                trace!(
+                    "{stage} exit code {exit_code} converted cleanly (no loss)"
+                );
             }
             ExitCode::from(exit_code)
         },
         Err(why) => {
             error!("unable to launch {}: {why:#}", NAME.as_ref());
+            debug!(
+                "gig failed before a final status was produced, exiting with 1"
+            );
             ExitCode::FAILURE
         },
     }
 }
 
-fn _main() -> anyhow::Result<ExitStatus> {
-    // Step 1: read config
-    let config = AppConfig::find_and_load()?;
-
-    // Step 2: work out if we're good to go, and where to run from
-    let Some(root) = config.get_root() else {
-        // If we're not good to go, do we have a fallback to run instead?
-        return match config.generate_fallback() {
-            Some(command) => {
-                info!("unable to locate required files, running fallback");
-                let status = command.status()?;
-                Ok(status)
-            },
-            None => Err(anyhow!("couldn't find required files")),
-        };
-    };
-
-    // Step 3: run before_run task/script
-    let command = config.generate_before_run(&root);
-    let status = command.status().context("failed to run before_run")?;
-    if !status.success() {
-        bail!("before_run returned a non-zero status");
-    }
-
-    // Step 4: build and spawn process
-    let command = config.generate_run(&root);
-    let status = command.status()?;
-    Ok(status)
-}
-
-#[derive(Debug, Deserialize)]
-struct AppConfig {
-    #[serde(default)]
-    required_files: Vec<PathBuf>,
-    #[serde(default)]
-    search_parents: bool,
-    before_run: BeforeRun,
-    run: Run,
-    #[serde(default)]
-    fallback: Option<Fallback>,
-}
-
-impl AppConfig {
-    fn find_and_load() -> anyhow::Result<Self> {
-        let config_name = format!("{}.toml", &*NAME);
-        let config_file_flow = [CWD.as_path(), *SYSTEM_WIDE_CONFIG_DIRECTORY]
-            .iter()
-            .try_for_each(|&dir| {
-                let config_file = dir.join(&config_name);
-                debug!("checking if {} exists", config_file.display());
-                if config_file.exists() {
-                    info!("found {}", config_file.display());
-                    return ControlFlow::Break(config_file);
-                }
-                ControlFlow::Continue(())
-            });
-        let config_file = match config_file_flow {
-            ControlFlow::Break(path) => path,
-            ControlFlow::Continue(()) => bail!("unable to find config file"),
-        };
-
-        let config = fs::read_to_string(&config_file).with_context(|| {
-            format!("couldn't read {}", config_file.display())
-        })?;
-        let config = toml::from_str::<AppConfig>(&config)?;
-        config.lint();
-        Ok(config)
-    }
-
-    fn get_root(&self) -> Option<Cow<Path>> {
-        let files_exist_in = |dir: &Path, files: &[PathBuf]| {
-            files.iter().all(|file_name| dir.join(file_name).exists())
-        };
-
-        if !self.required_files.is_empty() {
-            if self.search_parents {
-                let mut dir: &Path = &CWD;
-                if files_exist_in(dir, &self.required_files) {
-                    return Some(dir.into());
-                }
-                // Can't use while-let with break values, so we overcome
-                loop {
-                    match dir.parent() {
-                        Some(dir)
-                            if files_exist_in(dir, &self.required_files) =>
-                        {
-                            break Some(dir.to_owned().into());
-                        },
-                        Some(new_dir) => dir = new_dir,
-                        None => break None,
-                    }
-                }
-            } else {
-                files_exist_in(&CWD, &self.required_files)
-                    .then_some(Cow::<Path>::Borrowed(&*CWD))
-            }
+fn init_logging() {
+    let json_logs = env::var("GIG_LOG_FORMAT")
+        .is_ok_and(|format| format.eq_ignore_ascii_case("json"));
+    let color = *COLOR;
+    let quiet = env::var_os("GIG_QUIET").is_some();
+    let builder = || {
+        let mut builder = env_logger::builder();
+        if quiet {
+            // Overrides GIG_LOG entirely, so it isn't parsed at all
+            builder.filter_level(LevelFilter::Error);
         } else {
-            Some(Cow::<Path>::Borrowed(&*CWD))
-        }
-    }
-
-    fn generate_before_run(&self, root: &Path) -> LoggedCommand {
-        let mut command = match &self.before_run {
-            BeforeRun::Command(cmd_str) => {
-                let mut iter = Shlex::new(cmd_str);
-                let mut command = Command::new(iter.next().unwrap());
-                command.args(iter);
-                command.envs(env::vars_os());
-                command
-            },
-            BeforeRun::ScriptPath(path) => {
-                let mut command = Command::new(path);
-                command.envs(env::vars_os());
-                command
-            },
-        };
-        command.current_dir(root);
-        LoggedCommand(command)
-    }
-
-    fn generate_run(&self, root: &Path) -> LoggedCommand {
-        let program: Cow<Path> = match &self.run {
-            Run::SubcommandOf(this) => Path::new(this).into(),
-            Run::PrependFolder(folder) => {
-                let exe_name: Cow<str> = if cfg!(windows) {
-                    format!("{}.exe", NAME.as_ref()).into()
+            builder
+                // Dry runs aren't much use if you can't see what would've been
+                // run, same goes for timings
+                .filter_level(if *DRY_RUN || *TIMING {
+                    LevelFilter::Info
                 } else {
-                    NAME.as_ref().into()
-                };
-                folder.join(Path::new(exe_name.as_ref())).into()
-            },
-            Run::Executable(this) => this.into(),
-        };
-
-        let mut command = Command::new(program.as_os_str());
-        if matches!(&self.run, Run::SubcommandOf(_)) {
-            command.arg(NAME.as_ref());
+                    LevelFilter::Warn
+                })
+                .parse_env(Env::new().filter("GIG_LOG"));
         }
-        command.args(env::args_os().skip(1));
-        command.envs(env::vars_os());
-        command.current_dir(root);
-        LoggedCommand(command)
-    }
-
-    fn generate_fallback(&self) -> Option<LoggedCommand> {
-        self.fallback.as_ref().map(|fallback| {
-            let command = match &fallback.path {
-                Some(path) => {
-                    let mut command = Command::new(path);
-                    command.args(env::args_os().skip(1));
-                    command.envs(env::vars_os());
-                    command
-                },
-                None => {
-                    // Re-run command without GIG in $PATH
-                    #[cfg(windows)]
-                    const PATH_SEP_BYTE: u8 = b';';
-                    #[cfg(not(windows))]
-                    const PATH_SEP_BYTE: u8 = b':';
-                    #[cfg(windows)]
-                    let path_sep = OsStr::new(";");
-                    #[cfg(not(windows))]
-                    let path_sep = OsStr::new(":");
-
-                    let gig_path = env::current_exe().unwrap();
-                    let gig_dir = gig_path
-                        .parent()
-                        .unwrap();
-                    let gig_dir_bytes = gig_dir.as_os_str().as_encoded_bytes();
-
-                    let path = env::var_os("PATH").expect("$PATH unset");
-                    let path_bytes = path.as_encoded_bytes();
-                    let path_parts = path_bytes
-                        .split(|&byte| byte == PATH_SEP_BYTE)
-                        .filter(|&slice| {
-                            let remove = if cfg!(windows) {
-                                // Windows has to be case insensitive
-                                // These conversions compile to zero-cost I think so this is fine to just call here
-                                // SAFETY: see below in .map()
-                                let slice = unsafe { OsStr::from_encoded_bytes_unchecked(slice) };
-                                !slice.eq_ignore_ascii_case(gig_dir)
-                            } else {
-                                slice != gig_dir_bytes
-                            };
-
-                            if remove {
-                                debug!("removing {} from $PATH", gig_dir.display());
-                            }
-                            remove
-                        })
-                        .map(|slice|
-                            // SAFETY: we are calling
-                            // OsStr::from_encoded_bytes_unchecked on bytes
-                            // made by OsStr::as_encoded_bytes, only having
-                            // split on valid UTF-8 characters.
-                            // Also, I'm basically doing the example code from
-                            // the Rust docs of
-                            // OsStr::from_encoded_bytes_unchecked lol
-                            unsafe { OsStr::from_encoded_bytes_unchecked(slice) }
-                        )
-                        .collect::<Vec<_>>();
-                    let new_path = path_parts.join(path_sep);
-                    trace!(
-                        "$PATH before:\n{}\n$PATH after:\n{}",
-                        path.to_string_lossy(),
-                        new_path.to_string_lossy(),
+        builder
+            .write_style(if color {
+                WriteStyle::Always
+            } else {
+                WriteStyle::Never
+            })
+            .format(move |buf, record| {
+                if json_logs {
+                    let timestamp =
+                        humantime::format_rfc3339_millis(SystemTime::now());
+                    return writeln!(
+                        buf,
+                        "{{\"timestamp\":{},\"name\":{},\"level\":{},\"\
+                         message\":{}}}",
+                        JsonString(&timestamp.to_string()),
+                        JsonString(NAME.as_ref()),
+                        JsonString(record.level().as_str()),
+                        JsonString(&record.args().to_string()),
                     );
-
-                    let mut command = Command::new(NAME.as_ref());
-                    command.args(env::args_os().skip(1));
-                    command.envs(env::vars_os());
-                    // Overwrite $PATH with our edited one
-                    command.env("PATH", new_path);
-                    command
-                },
-            };
-            LoggedCommand(command)
-        })
-    }
-
-    fn lint(&self) {
-        if self.required_files.is_empty() && self.search_parents {
-            warn!(
-                "search_parents has no effect if there are no required files"
-            );
-        }
-        if self.required_files.is_empty() && self.fallback.is_some() {
-            warn!("fallback has no effect if there are no required files");
-        }
-    }
-}
-
-#[derive(Debug)]
-enum BeforeRun {
-    Command(String),
-    ScriptPath(PathBuf),
-}
-
-impl<'de> Deserialize<'de> for BeforeRun {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        struct BeforeRunVisitor;
-
-        impl<'de> Visitor<'de> for BeforeRunVisitor {
-            type Value = BeforeRun;
-
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("before_run table")
-            }
-
-            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-            where
-                A: MapAccess<'de>,
-            {
-                let (key, value) =
-                    map.next_entry::<String, String>()?.ok_or_else(|| {
-                        A::Error::custom("empty before_run table")
-                    })?;
-                match key.as_str() {
-                    "command" => {
-                        if !value.is_empty() {
-                            Ok(BeforeRun::Command(value))
-                        } else {
-                            Err(A::Error::custom("command can't be empty"))
-                        }
+                }
+                let mut style = buf.style();
+                match record.level() {
+                    _ if !color => {},
+                    Level::Error => {
+                        style.set_color(Color::Red);
                     },
-                    "script_path" => {
-                        let path = PathBuf::from(value);
-                        if path.is_file() {
-                            Ok(BeforeRun::ScriptPath(path))
-                        } else {
-                            Err(A::Error::custom("invalid path (not a file)"))
-                        }
+                    Level::Warn => {
+                        style.set_color(Color::Yellow);
                     },
-                    unknown => Err(A::Error::custom(format_args!(
-                        "unrecognised key \"{unknown}\", expected \"command\" \
-                         or \"script_path\""
-                    ))),
-                }
-            }
-        }
-
-        deserializer.deserialize_map(BeforeRunVisitor)
-    }
-}
-
-#[derive(Debug)]
-enum Run {
-    SubcommandOf(String),
-    PrependFolder(PathBuf),
-    Executable(PathBuf),
-}
-
-impl<'de> Deserialize<'de> for Run {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        struct RunVisitor;
-
-        impl<'de> Visitor<'de> for RunVisitor {
-            type Value = Run;
-
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("run table")
-            }
-
-            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-            where
-                A: MapAccess<'de>,
-            {
-                let (key, value) = map
-                    .next_entry::<String, String>()?
-                    .ok_or_else(|| A::Error::custom("empty run table"))?;
-                match key.as_str() {
-                    "subcommand_of" => Ok(Run::SubcommandOf(value)),
-                    "path" => {
-                        if value.ends_with('/') {
-                            Ok(Run::PrependFolder(value.into()))
-                        } else {
-                            Ok(Run::Executable(value.into()))
-                        }
+                    Level::Info => {},
+                    Level::Debug | Level::Trace => {
+                        style.set_dimmed(true);
                     },
-                    unknown => Err(A::Error::custom(format_args!(
-                        "unrecognised key \"{unknown}\", expected \
-                         \"subcommand_of\" or \"path\""
-                    ))),
                 }
-            }
-        }
+                writeln!(
+                    buf,
+                    "[{} {}]: {}",
+                    NAME.as_ref(),
+                    style.value(record.level()),
+                    record.args()
+                )
+            });
+        builder
+    };
 
-        deserializer.deserialize_map(RunVisitor)
+    let stderr = builder().build();
+    let max_level = stderr.filter();
+    let mut file_error = None;
+    let file = env::var_os("GIG_LOG_FILE").and_then(|path| {
+        let file = fs::OpenOptions::new().create(true).append(true).open(&path);
+        match file {
+            Ok(file) => Some(
+                builder()
+                    .target(Target::Pipe(Box::new(file)))
+                    .write_style(WriteStyle::Never)
+                    .build(),
+            ),
+            Err(why) => {
+                file_error = Some((path, why));
+                None
+            },
+        }
+    });
+    log::set_boxed_logger(Box::new(TeeLogger { stderr, file }))
+        .expect("logger shouldn't have been set yet");
+    log::set_max_level(max_level);
+
+    if let Some((path, why)) = file_error {
+        warn!(
+            "couldn't open {} for logging, only logging to stderr: {why}",
+            Path::new(&path).display(),
+        );
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Fallback {
-    #[serde(default)]
-    path: Option<PathBuf>,
+/// Logs to stderr, and additionally to a file if one is given
+struct TeeLogger {
+    stderr: Logger,
+    file: Option<Logger>,
 }
 
-#[derive(Debug)]
-struct LoggedCommand(Command);
-
-impl LoggedCommand {
-    fn status(mut self) -> anyhow::Result<ExitStatus> {
-        info!("running: {self}");
-        self.0
-            .status()
-            .with_context(|| format!("failed to invoke {self}"))
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
     }
-}
 
-impl fmt::Display for LoggedCommand {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let program = self.0.get_program().to_str().expect(
-            "program should be UTF-8 when it was made from UTF-8 originally",
-        );
-        let args = self
-            .0
-            .get_args()
-            .map(OsStr::to_string_lossy)
-            .collect::<Vec<_>>()
-            .join(" ");
-        let cwd = self
-            .0
-            .get_current_dir()
-            .filter(|cwd| *cwd != *CWD)
-            .map_or(String::new(), |cwd| format!(" in {}", cwd.display()));
-        write!(
-            f,
-            "`{program}{space}{args}`{cwd}",
-            space = if !args.is_empty() { " " } else { "" },
-        )
+    fn log(&self, record: &Record) {
+        self.stderr.log(record);
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
     }
-}
-
-#[cfg(test)]
-mod unit_tests {
-    use crate::AppConfig;
 
-    #[test]
-    fn deserialise_example() {
-        let app_config =
-            toml::from_str::<AppConfig>(include_str!("../config.example.toml"))
-                .expect("should deserialise");
-        dbg!(app_config);
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
     }
 }